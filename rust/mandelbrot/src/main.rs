@@ -4,6 +4,9 @@ extern crate crossbeam;
 extern crate num_cpus;
 extern crate rayon;
 
+mod progressive;
+mod stats;
+
 use clap::{App, Arg};
 use rayon::prelude::*;
 use std::time::{Instant, Duration};
@@ -38,6 +41,8 @@ pub struct MandelConfig {
     pub num_threads: u32,
     pub num_of_runs: u32,
     pub code_config: u32,
+    pub block_size: u32,
+    pub show_stats: bool,
 }
 
 pub fn do_runs(mandel_config: &MandelConfig, image: &mut [u32]) {
@@ -48,11 +53,12 @@ pub fn do_runs(mandel_config: &MandelConfig, image: &mut [u32]) {
     let mut pixel_parallel_time = Duration::new(100, 0);
     let mut row_parallel_time = Duration::new(100, 0);
     let mut crossbeam_parallel_time = Duration::new(100, 0);
+    let mut progressive_time = Duration::new(100, 0);
 
     if (mandel_config.code_config == 0) || (mandel_config.code_config == 2) {
         for _ in 0..num_runs {
             let serial_start = Instant::now();
-            mandelbrot_serial(&mandel_config, image);
+            mandelbrot_serial(mandel_config, image);
             let serial_end = Instant::now();
 
             serial_time = std::cmp::min(serial_time, serial_end.duration_since(serial_start));
@@ -60,7 +66,7 @@ pub fn do_runs(mandel_config: &MandelConfig, image: &mut [u32]) {
 
         println!(
             "[mandelbrot-rust serial]: \t[{:?}] ms",
-            serial_time.as_micros() as f64 / 1000 as f64
+            serial_time.as_micros() as f64 / 1000_f64
         );
     }
 
@@ -68,7 +74,7 @@ pub fn do_runs(mandel_config: &MandelConfig, image: &mut [u32]) {
 
         for _ in 0..num_runs {
             let rayon_pixel_start = Instant::now();
-            rayon_mandelbrot_pixel(&mandel_config, image);
+            rayon_mandelbrot_pixel(mandel_config, image);
             let rayon_pixel_end = Instant::now();
 
             pixel_parallel_time = std::cmp::min(pixel_parallel_time, rayon_pixel_end.duration_since(rayon_pixel_start));
@@ -76,7 +82,7 @@ pub fn do_runs(mandel_config: &MandelConfig, image: &mut [u32]) {
 
         println!(
             "[mandelbrot-rust pixel]: \t\t[{:?}] ms",
-            pixel_parallel_time.as_micros() as f64 / 1000 as f64
+            pixel_parallel_time.as_micros() as f64 / 1000_f64
         );
         if mandel_config.code_config == 0 {
             println!(
@@ -90,7 +96,7 @@ pub fn do_runs(mandel_config: &MandelConfig, image: &mut [u32]) {
 
         for _ in 0..num_runs {
             let rayon_row_start = Instant::now();
-            rayon_mandelbrot_row(&mandel_config, image);
+            rayon_mandelbrot_row(mandel_config, image);
             let rayon_row_end = Instant::now();
 
             row_parallel_time = std::cmp::min(row_parallel_time, rayon_row_end.duration_since(rayon_row_start));
@@ -98,7 +104,7 @@ pub fn do_runs(mandel_config: &MandelConfig, image: &mut [u32]) {
 
         println!(
             "[mandelbrot-rust row]: \t\t\t[{:?}] ms",
-            row_parallel_time.as_micros() as f64 / 1000 as f64
+            row_parallel_time.as_micros() as f64 / 1000_f64
         );
 
         if mandel_config.code_config == 0 {
@@ -110,7 +116,7 @@ pub fn do_runs(mandel_config: &MandelConfig, image: &mut [u32]) {
 
         for _ in 0..num_runs {
             let crossbeam_row_start = Instant::now();
-            crossbeam_manderlbrot_row(&mandel_config, image);
+            crossbeam_manderlbrot_row(mandel_config, image);
             let crossbeam_row_end = Instant::now();
 
             crossbeam_parallel_time = std::cmp::min(crossbeam_parallel_time, crossbeam_row_end.duration_since(crossbeam_row_start));
@@ -118,7 +124,7 @@ pub fn do_runs(mandel_config: &MandelConfig, image: &mut [u32]) {
 
         println!(
             "[mandelbrot-rust crossbeam row]: \t[{:?}] ms",
-            crossbeam_parallel_time.as_micros() as f64 / 1000 as f64
+            crossbeam_parallel_time.as_micros() as f64 / 1000_f64
         );
 
         if mandel_config.code_config == 0 {
@@ -127,6 +133,83 @@ pub fn do_runs(mandel_config: &MandelConfig, image: &mut [u32]) {
                 serial_time.as_micros() as f64/ crossbeam_parallel_time.as_micros() as f64, mandel_config.num_threads
             );
         }
+
+        //////////////////////
+        // Progressive Test
+
+        let mut progressive_image: Vec<u32> = vec![0; image.len()];
+        let mut frames = Vec::new();
+
+        for _ in 0..num_runs {
+            frames.clear();
+            let progressive_start = Instant::now();
+            progressive::rayon_mandelbrot_progressive(mandel_config, &mut progressive_image, |frame, _| {
+                frames.push(*frame)
+            });
+            let progressive_end = Instant::now();
+
+            progressive_time = std::cmp::min(progressive_time, progressive_end.duration_since(progressive_start));
+        }
+
+        let last_frame = frames.last().unwrap();
+        let differing = progressive_image
+            .par_iter()
+            .zip(image.par_iter())
+            .filter(|(p, e)| p != e)
+            .count();
+
+        println!(
+            "[mandelbrot-rust progressive]: \t[{:?}] ms",
+            progressive_time.as_micros() as f64 / 1000_f64
+        );
+        println!(
+            "++++ \t\t({} levels, {:.2}% pixels evaluated, {} pixels differ)",
+            last_frame.level + 1,
+            100.0 * last_frame.pixels_computed as f64 / image.len() as f64,
+            differing
+        );
+        for frame in frames.iter() {
+            println!(
+                "++++ \t\t  level {}: block {}, {} active blocks, {} pixels evaluated",
+                frame.level, frame.block_size, frame.active_blocks, frame.pixels_computed
+            );
+        }
+
+        // The last frame is approximate, so this is no speedup over the exact
+        // serial render, only how much sooner the final frame is on screen
+        if mandel_config.code_config == 0 {
+            println!(
+                "++++ \t\t({:.2}x less time to the final frame than the exact serial render, {:?} threads)\n",
+                serial_time.as_micros() as f64/ progressive_time.as_micros() as f64, mandel_config.num_threads
+            );
+        }
+    }
+
+    //////////////////////
+    // Image statistics
+
+    if mandel_config.show_stats {
+        // The image holds the exact result of the last full (non progressive) run
+        let image_stats = stats::image_stats(mandel_config.max_iter, image);
+        stats::print_stats(&image_stats);
+
+        let mut timings = Vec::new();
+        if (mandel_config.code_config == 0) || (mandel_config.code_config == 2) {
+            timings.push(("serial", serial_time));
+        }
+        if (mandel_config.code_config == 0) || (mandel_config.code_config == 1) {
+            timings.push(("pixel", pixel_parallel_time));
+            timings.push(("row", row_parallel_time));
+            timings.push(("crossbeam row", crossbeam_parallel_time));
+        }
+
+        for (name, time) in timings {
+            println!(
+                "[mandelbrot-rust {}]: \t[{:.3}] Giters/s",
+                name,
+                image_stats.iters_per_sec(time) / 1e9
+            );
+        }
     }
 }
 
@@ -194,6 +277,18 @@ pub fn parse_arguments() -> MandelConfig {
                 .value_name("VIEW_NUM")
                 .help("the view number to observe (default: 1)"),
         )
+        .arg(
+            Arg::with_name("BLOCK_SIZE")
+                .short("b")
+                .long("block")
+                .value_name("BLOCK_SIZE")
+                .help("coarse block size of the progressive render (default: 16)"),
+        )
+        .arg(
+            Arg::with_name("STATS")
+                .long("stats")
+                .help("print the escape count histogram and iterations/second of every run"),
+        )
         .arg(
             Arg::with_name("CODE")
                 .short("c")
@@ -218,6 +313,8 @@ pub fn parse_arguments() -> MandelConfig {
         value_t!(matches.value_of("NUMBER_OF_THREADS"), u32).unwrap_or(max_threads as u32);
     let view = value_t!(matches.value_of("VIEW_NUM"), u32).unwrap_or(1);
     let code_config = value_t!(matches.value_of("CODE"), u32).unwrap_or(0);
+    let block_size = value_t!(matches.value_of("BLOCK_SIZE"), u32).unwrap_or(16);
+    let show_stats = matches.is_present("STATS");
 
     // Check if values are correct for the mandelbrot program
     assert!(re1 < re2);
@@ -228,13 +325,14 @@ pub fn parse_arguments() -> MandelConfig {
     assert!(view < 7);
     assert!(num_of_runs > 0);
     assert!(code_config < 3);
+    assert!(block_size > 0);
 
     // Find new scaled values for view
     let (x0, x1, y0, y1) = scale_and_shift(re1, re2, img1, img2, view);
 
     //
-    println!("Configuration: \nre1: {:.3}, re2: {:.3}, img1: {:.3}, img2: {:.3}, max_iter: {}, img_size: {}, num_threads: {}, num_of_runs: {}, view: {}, code_config: {} \n",
-        x0, x1, y0, y1, max_iter, img_size, num_threads, num_of_runs, view, code_config);
+    println!("Configuration: \nre1: {:.3}, re2: {:.3}, img1: {:.3}, img2: {:.3}, max_iter: {}, img_size: {}, num_threads: {}, num_of_runs: {}, view: {}, code_config: {}, block_size: {}, show_stats: {} \n",
+        x0, x1, y0, y1, max_iter, img_size, num_threads, num_of_runs, view, code_config, block_size, show_stats);
 
     // Calculate the step size
     let x_step = (x1 - x0) / (img_size as f32);
@@ -246,13 +344,15 @@ pub fn parse_arguments() -> MandelConfig {
         re2: x1,
         img1: y0,
         img2: y1,
-        x_step: x_step,
-        y_step: y_step,
-        max_iter: max_iter,
-        img_size: img_size,
-        num_threads: num_threads,
-        num_of_runs: num_of_runs,
-        code_config: code_config,
+        x_step,
+        y_step,
+        max_iter,
+        img_size,
+        num_threads,
+        num_of_runs,
+        code_config,
+        block_size,
+        show_stats,
     }
 }
 
@@ -265,9 +365,9 @@ pub fn scale_and_shift(
     view: u32,
 ) -> (f32, f32, f32, f32) {
     // Same Magic arrays as for C++ code for different views
-    let scale = vec![0.01, 1.0, 0.015, 0.02, 0.02, 0.02, 0.002];
-    let shift_x = vec![0.0, 0.0, -0.98, 0.35, 0.0, -1.5, -1.4];
-    let shift_y = vec![0.0, 0.0, 0.30, 0.05, 0.73, 0.0, 0.0];
+    let scale = [0.01, 1.0, 0.015, 0.02, 0.02, 0.02, 0.002];
+    let shift_x = [0.0, 0.0, -0.98, 0.35, 0.0, -1.5, -1.4];
+    let shift_y = [0.0, 0.0, 0.30, 0.05, 0.73, 0.0, 0.0];
 
     // Convert u32 to usize
     let view_num = view as usize;
@@ -290,7 +390,7 @@ pub fn scale_and_shift(
     y0 += shift_y[view_num];
     y1 += shift_y[view_num];
 
-    return (x0, x1, y0, y1);
+    (x0, x1, y0, y1)
 }
 
 /*************************************
@@ -370,7 +470,7 @@ pub fn mandel_iter(max_iter: u32, c_re: f32, c_im: f32) -> u32 {
         
         z_re = c_re + new_re;
         z_im = c_im + new_im;
-        iter = iter + 1;
+        iter += 1;
     }
     iter
 }
\ No newline at end of file
@@ -0,0 +1,277 @@
+use crate::{mandel_iter, MandelConfig};
+use rayon::prelude::*;
+
+/*************************************
+ * Progressive (adaptive) Mandelbrot
+ *************************************/
+// The image is first tiled into coarse blocks whose four corner pixels are
+// computed exactly and the rest of the block is filled with the top-left
+// value, giving a cheap preview. Every following level looks at the corners
+// of the still active blocks: if they all agree the block is assumed to be
+// uniform and is left alone, otherwise it is split into four and the children
+// are seeded the same way. Blocks of at most 2x2 pixels are exact since all of
+// their pixels are corners, so the last frame only differs from the serial
+// image where a feature slipped between agreeing corners.
+
+// A rectangle of pixels, in image coordinates
+#[derive(Copy, Clone)]
+struct Block {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+// Summary of one refinement level, handed out after the level is written
+#[derive(Copy, Clone, Debug)]
+pub struct FrameInfo {
+    pub level: u32,
+    pub block_size: u32,
+    pub active_blocks: usize,
+    pub pixels_computed: u64,
+}
+
+pub struct ProgressiveRenderer<'a> {
+    mandel_config: &'a MandelConfig,
+    image: &'a mut [u32],
+    band_height: u32,
+    // Active blocks of every band of `band_height` rows
+    bands: Vec<Vec<Block>>,
+    level: u32,
+    block_size: u32,
+    pixels_computed: u64,
+    done: bool,
+}
+
+impl<'a> ProgressiveRenderer<'a> {
+    pub fn new(mandel_config: &'a MandelConfig, image: &'a mut [u32]) -> ProgressiveRenderer<'a> {
+        let size = mandel_config.img_size;
+        let band_height = mandel_config.block_size.min(size);
+        assert!(band_height > 0);
+        assert_eq!(image.len(), (size * size) as usize);
+
+        // Tile the image with the coarse blocks, clipped at the border
+        let bands = (0..size)
+            .step_by(band_height as usize)
+            .map(|y| {
+                (0..size)
+                    .step_by(band_height as usize)
+                    .map(|x| Block {
+                        x,
+                        y,
+                        w: band_height.min(size - x),
+                        h: band_height.min(size - y),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        ProgressiveRenderer {
+            mandel_config,
+            image,
+            band_height,
+            bands,
+            level: 0,
+            block_size: band_height,
+            pixels_computed: 0,
+            done: false,
+        }
+    }
+
+    // The current (partially refined) image
+    pub fn image(&self) -> &[u32] {
+        self.image
+    }
+
+    // Computes the next level and returns its summary, None once every block
+    // has converged.
+    pub fn step(&mut self) -> Option<FrameInfo> {
+        if self.done {
+            return None;
+        }
+
+        let mandel_config = self.mandel_config;
+        let band_height = self.band_height;
+        let band_len = (band_height * mandel_config.img_size) as usize;
+        let first_level = self.level == 0;
+
+        let computed: u64 = self
+            .bands
+            .par_iter_mut()
+            .zip(self.image.par_chunks_mut(band_len))
+            .enumerate()
+            .map(|(band_num, (blocks, band))| {
+                let band_y = band_num as u32 * band_height;
+                if first_level {
+                    blocks
+                        .iter()
+                        .map(|block| seed_block(mandel_config, band, band_y, block, None))
+                        .sum()
+                } else {
+                    let (next, computed) = refine_band(mandel_config, band, band_y, blocks);
+                    *blocks = next;
+                    computed
+                }
+            })
+            .sum();
+
+        let active_blocks = self.bands.iter().map(|blocks| blocks.len()).sum();
+        if !first_level {
+            self.block_size = self.block_size.div_ceil(2);
+        }
+
+        self.pixels_computed += computed;
+        self.done = active_blocks == 0;
+
+        let frame = FrameInfo {
+            level: self.level,
+            block_size: self.block_size,
+            active_blocks,
+            pixels_computed: self.pixels_computed,
+        };
+        self.level += 1;
+
+        Some(frame)
+    }
+}
+
+impl<'a> Iterator for ProgressiveRenderer<'a> {
+    type Item = FrameInfo;
+
+    fn next(&mut self) -> Option<FrameInfo> {
+        self.step()
+    }
+}
+
+// Parallel progressive rendering with Rayon, `on_frame` sees every
+// intermediate image. Returns the summary of the final frame.
+pub fn rayon_mandelbrot_progressive<F>(
+    mandel_config: &MandelConfig,
+    image: &mut [u32],
+    mut on_frame: F,
+) -> FrameInfo
+where
+    F: FnMut(&FrameInfo, &[u32]),
+{
+    let mut renderer = ProgressiveRenderer::new(mandel_config, image);
+    let mut last = None;
+
+    while let Some(frame) = renderer.step() {
+        on_frame(&frame, renderer.image());
+        last = Some(frame);
+    }
+
+    last.unwrap()
+}
+
+// Splits the disagreeing blocks of a band and returns the new active blocks
+// together with the number of pixels evaluated.
+fn refine_band(
+    mandel_config: &MandelConfig,
+    band: &mut [u32],
+    band_y: u32,
+    blocks: &[Block],
+) -> (Vec<Block>, u64) {
+    let size = mandel_config.img_size;
+    let mut next = Vec::new();
+    let mut computed = 0;
+
+    for block in blocks {
+        // Every pixel of a 2x2 block is a corner, so it is already exact
+        if block.w <= 2 && block.h <= 2 {
+            continue;
+        }
+
+        let corners = corners(block);
+        let first = band[local_index(size, band_y, corners[0])];
+        if corners
+            .iter()
+            .all(|&corner| band[local_index(size, band_y, corner)] == first)
+        {
+            continue;
+        }
+
+        let left_w = block.w.div_ceil(2);
+        let top_h = block.h.div_ceil(2);
+        let children = [
+            (block.x, block.y, left_w, top_h),
+            (block.x + left_w, block.y, block.w - left_w, top_h),
+            (block.x, block.y + top_h, left_w, block.h - top_h),
+            (block.x + left_w, block.y + top_h, block.w - left_w, block.h - top_h),
+        ];
+
+        for &(x, y, w, h) in children.iter() {
+            if w == 0 || h == 0 {
+                continue;
+            }
+            let child = Block { x, y, w, h };
+            computed += seed_block(mandel_config, band, band_y, &child, Some(block));
+            if w > 2 || h > 2 {
+                next.push(child);
+            }
+        }
+    }
+
+    (next, computed)
+}
+
+// Computes the corners of `block` and fills the rest of it with the top-left
+// value. Corners shared with the `parent` block are already exact and are not
+// recomputed. Returns the number of pixels evaluated.
+fn seed_block(
+    mandel_config: &MandelConfig,
+    band: &mut [u32],
+    band_y: u32,
+    block: &Block,
+    parent: Option<&Block>,
+) -> u64 {
+    let size = mandel_config.img_size;
+    let mut corners = corners(block);
+    // Degenerate blocks repeat corners, only evaluate them once
+    corners.sort_by_key(|&(x, y)| (y, x));
+    let mut computed = 0;
+
+    let mut values = [0; 4];
+    for (n, &(x, y)) in corners.iter().enumerate() {
+        let known = parent.is_some_and(|parent| is_corner(parent, x, y));
+        values[n] = if n > 0 && corners[n - 1] == (x, y) {
+            values[n - 1]
+        } else if known {
+            band[local_index(size, band_y, (x, y))]
+        } else {
+            computed += 1;
+            let xf = mandel_config.re1 + x as f32 * mandel_config.x_step;
+            let yf = mandel_config.img1 + y as f32 * mandel_config.y_step;
+            mandel_iter(mandel_config.max_iter, xf, yf)
+        };
+    }
+
+    let fill = values[0];
+    for y in block.y..(block.y + block.h) {
+        let start = local_index(size, band_y, (block.x, y));
+        band[start..(start + block.w as usize)]
+            .iter_mut()
+            .for_each(|pixel| *pixel = fill);
+    }
+
+    for (n, &corner) in corners.iter().enumerate() {
+        band[local_index(size, band_y, corner)] = values[n];
+    }
+
+    computed
+}
+
+fn corners(block: &Block) -> [(u32, u32); 4] {
+    let x1 = block.x + block.w - 1;
+    let y1 = block.y + block.h - 1;
+    [(block.x, block.y), (x1, block.y), (block.x, y1), (x1, y1)]
+}
+
+fn is_corner(block: &Block, x: u32, y: u32) -> bool {
+    corners(block).contains(&(x, y))
+}
+
+// Index of an image pixel inside the band starting at row `band_y`
+fn local_index(size: u32, band_y: u32, (x, y): (u32, u32)) -> usize {
+    ((y - band_y) * size + x) as usize
+}
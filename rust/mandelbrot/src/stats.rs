@@ -0,0 +1,103 @@
+use rayon::prelude::*;
+use std::time::Duration;
+
+const STATS_CHUNK: usize = 16 * 1024;
+
+/*************************************
+ * Image statistics
+ *************************************/
+// Escape counts of a computed image. The escape count of a pixel is exactly
+// the number of iterations `mandel_iter` executed for it, so the histogram
+// also gives the total work of a view, which differs by orders of magnitude
+// between views.
+pub struct ImageStats {
+    // histogram[n] is the number of pixels that escaped after n iterations,
+    // histogram[max_iter] counts the interior points
+    pub histogram: Vec<u64>,
+    pub num_pixels: u64,
+    pub interior_points: u64,
+    pub total_iters: u64,
+}
+
+impl ImageStats {
+    pub fn interior_fraction(&self) -> f64 {
+        self.interior_points as f64 / self.num_pixels as f64
+    }
+
+    // Throughput of a run that computed the whole image in `time`
+    pub fn iters_per_sec(&self, time: Duration) -> f64 {
+        self.total_iters as f64 / time.as_secs_f64()
+    }
+}
+
+// Parallel histogram reduction: every task counts into its own histogram and
+// the histograms are added pairwise.
+pub fn image_stats(max_iter: u32, image: &[u32]) -> ImageStats {
+    let num_bins = max_iter as usize + 1;
+
+    let histogram = image
+        .par_chunks(STATS_CHUNK)
+        .fold(
+            || vec![0u64; num_bins],
+            |mut histogram, chunk| {
+                chunk
+                    .iter()
+                    .for_each(|&iters| histogram[iters as usize] += 1);
+                histogram
+            },
+        )
+        .reduce(
+            || vec![0u64; num_bins],
+            |mut left, right| {
+                left.iter_mut().zip(right.iter()).for_each(|(l, r)| *l += *r);
+                left
+            },
+        );
+
+    let total_iters = histogram
+        .iter()
+        .enumerate()
+        .map(|(iters, count)| iters as u64 * count)
+        .sum();
+
+    ImageStats {
+        interior_points: histogram[max_iter as usize],
+        num_pixels: image.len() as u64,
+        total_iters,
+        histogram,
+    }
+}
+
+// Prints the histogram folded into power of two buckets of escape counts
+pub fn print_stats(image_stats: &ImageStats) {
+    let max_iter = image_stats.histogram.len() - 1;
+
+    println!(
+        "[mandelbrot-rust stats]: \t{} pixels, {} total iterations, {:.2}% interior",
+        image_stats.num_pixels,
+        image_stats.total_iters,
+        100.0 * image_stats.interior_fraction()
+    );
+
+    let mut low = 0;
+    let mut high = 1;
+    while low < max_iter {
+        let high_clamped = std::cmp::min(high, max_iter);
+        let count: u64 = image_stats.histogram[low..high_clamped].iter().sum();
+        if count > 0 {
+            println!(
+                "++++ \t\t  escape [{}, {}): {} pixels ({:.2}%)",
+                low,
+                high_clamped,
+                count,
+                100.0 * count as f64 / image_stats.num_pixels as f64
+            );
+        }
+        low = high_clamped;
+        high *= 2;
+    }
+    println!(
+        "++++ \t\t  interior [{}]: {} pixels",
+        max_iter, image_stats.interior_points
+    );
+}
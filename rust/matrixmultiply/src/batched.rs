@@ -0,0 +1,105 @@
+use crate::element::{self, Element};
+use crate::{matmul_par_row, matmul_serial, print_speedup, random, time_kernel, MatMulConfig};
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use std::time::Duration;
+
+// A kernel called with (small, A batch, B batch, C batch)
+type BatchKernel<T> = fn(usize, &[T], &[T], &mut [T]);
+
+/*************************************
+ * Batched small matrices
+ *************************************/
+// Thousands of independent `small` x `small` products, stored one after the
+// other. Parallel across the batch, every product is a task; parallel within
+// each product, the batch is walked serially and every product is split by
+// rows like `matmul_par_row`, which spawns a few tasks of tiny work each.
+
+// Multiplies the `small` x `small` matrices of two batches
+pub fn batch_serial<T: Element>(small: usize, m_a: &[T], m_b: &[T], m_c: &mut [T]) {
+    let len = small * small;
+    m_c.chunks_mut(len)
+        .zip(m_a.chunks(len).zip(m_b.chunks(len)))
+        .for_each(|(c, (a, b))| matmul_serial(small, small, small, a, b, c));
+}
+
+pub fn batch_par_across<T: Element>(small: usize, m_a: &[T], m_b: &[T], m_c: &mut [T]) {
+    let len = small * small;
+    m_c.par_chunks_mut(len)
+        .zip(m_a.par_chunks(len).zip(m_b.par_chunks(len)))
+        .for_each(|(c, (a, b))| matmul_serial(small, small, small, a, b, c));
+}
+
+pub fn batch_par_within<T: Element>(small: usize, m_a: &[T], m_b: &[T], m_c: &mut [T]) {
+    let len = small * small;
+    m_c.chunks_mut(len)
+        .zip(m_a.chunks(len).zip(m_b.chunks(len)))
+        .for_each(|(c, (a, b))| matmul_par_row(small, small, small, a, b, c));
+}
+
+// Both levels, leaving the split to Rayon's work stealing
+pub fn batch_par_nested<T: Element>(small: usize, m_a: &[T], m_b: &[T], m_c: &mut [T]) {
+    let len = small * small;
+    m_c.par_chunks_mut(len)
+        .zip(m_a.par_chunks(len).zip(m_b.par_chunks(len)))
+        .for_each(|(c, (a, b))| matmul_par_row(small, small, small, a, b, c));
+}
+
+fn print_per_product<T: Element>(batch: usize, small: usize, time: Duration) {
+    let unit = if T::IS_INTEGER { "GOPS" } else { "GFLOPS" };
+    println!(
+        "++++ \t\t({:.2} {}, {:.0} ns per product)",
+        (2 * batch * small * small * small) as f64 / time.as_secs_f64() / 1e9,
+        unit,
+        time.as_nanos() as f64 / batch as f64
+    );
+}
+
+// `batch` products of random matrices
+pub fn do_batched_runs<T: Element>(matmul_config: &MatMulConfig, rng: &mut StdRng) {
+    let num_runs = matmul_config.num_of_runs;
+    let batch = matmul_config.batch as usize;
+    let small = matmul_config.small as usize;
+    let len = batch * small * small;
+
+    println!(
+        "Batch of {} products of {}x{} matrices",
+        batch, small, small
+    );
+
+    let m_a = random::random_matrix(rng, len);
+    let m_b = random::random_matrix(rng, len);
+    let mut serial_m_c = vec![T::default(); len];
+    let mut serial_time = Duration::new(100, 0);
+
+    if (matmul_config.code_config == 0) || (matmul_config.code_config == 2) {
+        serial_time = time_kernel("batch_serial", num_runs, || {
+            batch_serial(small, &m_a[..], &m_b[..], &mut serial_m_c[..])
+        });
+        print_per_product::<T>(batch, small, serial_time);
+        element::report_overflow("batch_serial", &serial_m_c[..]);
+        println!();
+    }
+
+    if (matmul_config.code_config == 0) || (matmul_config.code_config == 1) {
+        let kernels: [(&str, BatchKernel<T>); 3] = [
+            ("batch_par_across", batch_par_across),
+            ("batch_par_within", batch_par_within),
+            ("batch_par_nested", batch_par_nested),
+        ];
+
+        for (name, kernel) in kernels.iter() {
+            let mut m_c = vec![T::default(); len];
+            let par_time = time_kernel(name, num_runs, || {
+                kernel(small, &m_a[..], &m_b[..], &mut m_c[..])
+            });
+            print_per_product::<T>(batch, small, par_time);
+            element::report_overflow(name, &m_c[..]);
+
+            if matmul_config.code_config == 0 {
+                element::assert_matches(name, &m_c[..], &serial_m_c[..]);
+                print_speedup(matmul_config, serial_time, par_time);
+            }
+        }
+    }
+}
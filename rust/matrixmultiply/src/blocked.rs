@@ -0,0 +1,241 @@
+use crate::element::Element;
+use rayon::prelude::*;
+use std::fs;
+use std::time::{Duration, Instant};
+
+/*************************************
+ * Cache-blocked matrix multiply
+ *************************************/
+// C is cut into MC x NC output tiles and the shared dimension into KC slabs,
+// so the slab of B used by a tile stays in cache while every row of the tile
+// streams over it. The inner loop walks a row of B and a row of C, both
+// contiguous, instead of striding down the columns of B.
+
+// Tile sizes of the blocked kernels
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BlockSizes {
+    pub mc: usize,
+    pub kc: usize,
+    pub nc: usize,
+}
+
+impl BlockSizes {
+    pub fn default_sizes() -> BlockSizes {
+        BlockSizes {
+            mc: 64,
+            kc: 256,
+            nc: 512,
+        }
+    }
+
+    // Reads the sizes recorded in `path` by a previous auto-tune sweep, if any
+    pub fn load_tuned(path: &str) -> Option<BlockSizes> {
+        let contents = fs::read_to_string(path).ok()?;
+        let sizes: Vec<usize> = contents
+            .split_whitespace()
+            .take(3)
+            .map(|value| value.parse().ok())
+            .collect::<Option<Vec<usize>>>()?;
+
+        if sizes.len() == 3 && sizes.iter().all(|&size| size > 0) {
+            Some(BlockSizes {
+                mc: sizes[0],
+                kc: sizes[1],
+                nc: sizes[2],
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn save_tuned(&self, path: &str, m: usize, k: usize, n: usize, time: Duration) {
+        let contents = format!(
+            "{} {} {}\n# mc kc nc, tuned on {}x{}x{} in {:?} ms\n",
+            self.mc,
+            self.kc,
+            self.nc,
+            m,
+            k,
+            n,
+            time.as_micros() as f64 / 1000_f64
+        );
+        if let Err(err) = fs::write(path, contents) {
+            println!("Could not record the tile sizes in {}: {}", path, err);
+        }
+    }
+}
+
+// One MC x NC tile of C: its first row and column, and a slice of C for each
+// of its rows
+type Tile<'a, T> = (usize, usize, Vec<&'a mut [T]>);
+
+// Cuts C (m x n, row-major) into disjoint MC x NC output tiles
+fn output_tiles<'a, T>(m_c: &'a mut [T], n: usize, blocks: &BlockSizes) -> Vec<Tile<'a, T>> {
+    let mut tiles = Vec::new();
+
+    for (band, c_band) in m_c.chunks_mut(blocks.mc * n).enumerate() {
+        let mut band_tiles: Vec<Vec<&mut [T]>> = (0..n)
+            .step_by(blocks.nc)
+            .map(|_| Vec::with_capacity(blocks.mc))
+            .collect();
+
+        for mut c_row in c_band.chunks_mut(n) {
+            for tile in band_tiles.iter_mut() {
+                let width = std::cmp::min(blocks.nc, c_row.len());
+                let (c_seg, rest) = c_row.split_at_mut(width);
+                tile.push(c_seg);
+                c_row = rest;
+            }
+        }
+
+        tiles.extend(
+            band_tiles
+                .into_iter()
+                .enumerate()
+                .map(|(tile, c_rows)| (band * blocks.mc, tile * blocks.nc, c_rows)),
+        );
+    }
+
+    tiles
+}
+
+// Accumulates the tile of A (m x k) * B (k x n) whose first row and column
+// are `row_start` and `col_start` into `c_rows`, one slice of C per row.
+#[allow(clippy::too_many_arguments)]
+fn tile_kernel<T: Element>(
+    k: usize,
+    n: usize,
+    kc: usize,
+    m_a: &[T],
+    m_b: &[T],
+    row_start: usize,
+    col_start: usize,
+    c_rows: &mut [&mut [T]],
+) {
+    for pc in (0..k).step_by(kc) {
+        let k_end = std::cmp::min(pc + kc, k);
+
+        for (r, c_row) in c_rows.iter_mut().enumerate() {
+            let width = c_row.len();
+            let a_row = &m_a[RM!(row_start + r, pc, k)..RM!(row_start + r, k_end, k)];
+
+            for (index, a) in a_row.iter().enumerate() {
+                let b_start = RM!(pc + index, col_start, n);
+                c_row
+                    .iter_mut()
+                    .zip(m_b[b_start..(b_start + width)].iter())
+                    .for_each(|(c, b)| *c += *a * *b);
+            }
+        }
+    }
+}
+
+// C (m x n) = A (m x k) * B (k x n), all row-major
+pub fn matmul_blocked_serial<T: Element>(
+    m: usize,
+    k: usize,
+    n: usize,
+    blocks: &BlockSizes,
+    m_a: &[T],
+    m_b: &[T],
+    m_c: &mut [T],
+) {
+    assert_eq!(m_a.len(), m * k);
+    assert_eq!(m_b.len(), k * n);
+    assert_eq!(m_c.len(), m * n);
+
+    m_c.iter_mut().for_each(|c| *c = T::default());
+
+    output_tiles(m_c, n, blocks)
+        .into_iter()
+        .for_each(|(row_start, col_start, mut c_rows)| {
+            tile_kernel(k, n, blocks.kc, m_a, m_b, row_start, col_start, &mut c_rows)
+        });
+}
+
+// Parallel over the output tiles: every tile owns disjoint slices of the
+// rows of C and is accumulated in place as in the serial kernel.
+// C (m x n) = A (m x k) * B (k x n), all row-major
+pub fn matmul_blocked_par<T: Element>(
+    m: usize,
+    k: usize,
+    n: usize,
+    blocks: &BlockSizes,
+    m_a: &[T],
+    m_b: &[T],
+    m_c: &mut [T],
+) {
+    assert_eq!(m_a.len(), m * k);
+    assert_eq!(m_b.len(), k * n);
+    assert_eq!(m_c.len(), m * n);
+
+    m_c.par_iter_mut().for_each(|c| *c = T::default());
+
+    output_tiles(m_c, n, blocks)
+        .into_par_iter()
+        .for_each(|(row_start, col_start, mut c_rows)| {
+            tile_kernel(k, n, blocks.kc, m_a, m_b, row_start, col_start, &mut c_rows)
+        });
+}
+
+// Times the parallel blocked kernel over a grid of tile sizes and records the
+// fastest in `tune_file`, if given.
+pub fn autotune<T: Element>(
+    m: usize,
+    k: usize,
+    n: usize,
+    num_runs: u32,
+    tune_file: Option<&str>,
+    m_a: &[T],
+    m_b: &[T],
+) -> BlockSizes {
+    let mut m_c: Vec<T> = vec![T::default(); m * n];
+    let mut best = BlockSizes::default_sizes();
+    let mut best_time = Duration::new(10000, 0);
+
+    for &mc in [16, 32, 64, 128, 256].iter() {
+        for &kc in [64, 128, 256, 512].iter() {
+            for &nc in [128, 256, 512, 1024, 2048].iter() {
+                let blocks = BlockSizes { mc, kc, nc };
+                let mut tile_time = Duration::new(10000, 0);
+
+                for _ in 0..num_runs {
+                    let par_start = Instant::now();
+                    matmul_blocked_par(m, k, n, &blocks, m_a, m_b, &mut m_c[..]);
+                    let par_end = Instant::now();
+
+                    tile_time = std::cmp::min(tile_time, par_end.duration_since(par_start));
+                }
+
+                println!(
+                    "[matmul-rust tune]: \tmc={} kc={} nc={} \t[{:?}] ms",
+                    mc,
+                    kc,
+                    nc,
+                    tile_time.as_micros() as f64 / 1000_f64
+                );
+
+                if tile_time < best_time {
+                    best = blocks;
+                    best_time = tile_time;
+                }
+            }
+        }
+    }
+
+    match tune_file {
+        Some(path) => {
+            println!(
+                "++++ \t\t(best tiles mc={} kc={} nc={}, recorded in {})\n",
+                best.mc, best.kc, best.nc, path
+            );
+            best.save_tuned(path, m, k, n, best_time);
+        }
+        None => println!(
+            "++++ \t\t(best tiles mc={} kc={} nc={})\n",
+            best.mc, best.kc, best.nc
+        ),
+    }
+
+    best
+}
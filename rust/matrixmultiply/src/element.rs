@@ -0,0 +1,344 @@
+use std::convert::TryInto;
+use std::fmt::Debug;
+use std::iter::Sum;
+use std::num::Wrapping;
+use std::ops::{Add, AddAssign, Mul, Sub, SubAssign};
+
+/*************************************
+ * Matrix element types
+ *************************************/
+// Every kernel is generic over the element type, picked with --dtype. Plain
+// i32 overflows silently in release builds and panics in debug builds, so the
+// two explicit policies are Wrapping<i32>, which wraps in every build, and
+// Checked, which also wraps but remembers that it did.
+
+pub trait Element:
+    Copy
+    + Default
+    + Debug
+    + PartialEq
+    + Send
+    + Sync
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + AddAssign
+    + SubAssign
+    + Sum
+{
+    // Whether the type is an integer, whose results are exact and counted in
+    // operations rather than floating point operations
+    const IS_INTEGER: bool;
+
+    // Largest relative difference allowed between two kernels, results of
+    // integer types have to match exactly
+    const TOLERANCE: f64;
+
+    // Size of the raw little-endian representation in binary matrix files
+    const BYTES: usize;
+
+    fn from_i32(x: i32) -> Self;
+
+    // Values read from text files, integer types truncate
+    fn from_f64(x: f64) -> Self;
+
+    fn to_f64(self) -> f64;
+
+    // Values in Matrix Market files, exact but for i64 beyond 2^53 by default
+    fn from_text(text: &str) -> Option<Self> {
+        text.parse::<f64>().ok().map(Self::from_f64)
+    }
+
+    fn to_text(self) -> String {
+        self.to_f64().to_string()
+    }
+
+    fn to_le(self, out: &mut Vec<u8>);
+
+    fn from_le(bytes: &[u8]) -> Self;
+
+    // Whether computing this value overflowed, only known to Checked
+    fn overflowed(self) -> bool {
+        false
+    }
+}
+
+impl Element for i32 {
+    const IS_INTEGER: bool = true;
+    const TOLERANCE: f64 = 0.0;
+    const BYTES: usize = 4;
+
+    fn from_i32(x: i32) -> i32 {
+        x
+    }
+
+    fn from_f64(x: f64) -> i32 {
+        x as i32
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn to_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn from_le(bytes: &[u8]) -> i32 {
+        i32::from_le_bytes(bytes[..4].try_into().unwrap())
+    }
+}
+
+impl Element for i64 {
+    const IS_INTEGER: bool = true;
+    const TOLERANCE: f64 = 0.0;
+    const BYTES: usize = 8;
+
+    fn from_i32(x: i32) -> i64 {
+        x as i64
+    }
+
+    fn from_f64(x: f64) -> i64 {
+        x as i64
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_text(text: &str) -> Option<i64> {
+        text.parse::<i64>()
+            .ok()
+            .or_else(|| text.parse::<f64>().ok().map(i64::from_f64))
+    }
+
+    fn to_text(self) -> String {
+        self.to_string()
+    }
+
+    fn to_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn from_le(bytes: &[u8]) -> i64 {
+        i64::from_le_bytes(bytes[..8].try_into().unwrap())
+    }
+}
+
+impl Element for f32 {
+    const IS_INTEGER: bool = false;
+    const TOLERANCE: f64 = 1e-3;
+    const BYTES: usize = 4;
+
+    fn from_i32(x: i32) -> f32 {
+        x as f32
+    }
+
+    fn from_f64(x: f64) -> f32 {
+        x as f32
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn to_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn from_le(bytes: &[u8]) -> f32 {
+        f32::from_le_bytes(bytes[..4].try_into().unwrap())
+    }
+}
+
+impl Element for f64 {
+    const IS_INTEGER: bool = false;
+    const TOLERANCE: f64 = 1e-9;
+    const BYTES: usize = 8;
+
+    fn from_i32(x: i32) -> f64 {
+        x as f64
+    }
+
+    fn from_f64(x: f64) -> f64 {
+        x
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn to_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn from_le(bytes: &[u8]) -> f64 {
+        f64::from_le_bytes(bytes[..8].try_into().unwrap())
+    }
+}
+
+impl Element for Wrapping<i32> {
+    const IS_INTEGER: bool = true;
+    const TOLERANCE: f64 = 0.0;
+    const BYTES: usize = 4;
+
+    fn from_i32(x: i32) -> Wrapping<i32> {
+        Wrapping(x)
+    }
+
+    fn from_f64(x: f64) -> Wrapping<i32> {
+        Wrapping(x as i32)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.0 as f64
+    }
+
+    fn to_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.0.to_le_bytes());
+    }
+
+    fn from_le(bytes: &[u8]) -> Wrapping<i32> {
+        Wrapping(i32::from_le_bytes(bytes[..4].try_into().unwrap()))
+    }
+}
+
+// i32 with a sticky overflow flag: the value wraps like Wrapping<i32>, and
+// every value computed from an overflowed one is marked as well.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Checked {
+    pub value: i32,
+    pub overflow: bool,
+}
+
+impl Add for Checked {
+    type Output = Checked;
+
+    fn add(self, other: Checked) -> Checked {
+        let (value, overflow) = self.value.overflowing_add(other.value);
+        Checked {
+            value,
+            overflow: overflow || self.overflow || other.overflow,
+        }
+    }
+}
+
+impl Sub for Checked {
+    type Output = Checked;
+
+    fn sub(self, other: Checked) -> Checked {
+        let (value, overflow) = self.value.overflowing_sub(other.value);
+        Checked {
+            value,
+            overflow: overflow || self.overflow || other.overflow,
+        }
+    }
+}
+
+impl Mul for Checked {
+    type Output = Checked;
+
+    fn mul(self, other: Checked) -> Checked {
+        let (value, overflow) = self.value.overflowing_mul(other.value);
+        Checked {
+            value,
+            overflow: overflow || self.overflow || other.overflow,
+        }
+    }
+}
+
+impl AddAssign for Checked {
+    fn add_assign(&mut self, other: Checked) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Checked {
+    fn sub_assign(&mut self, other: Checked) {
+        *self = *self - other;
+    }
+}
+
+impl Sum for Checked {
+    fn sum<I: Iterator<Item = Checked>>(iter: I) -> Checked {
+        iter.fold(Checked::default(), |acc, x| acc + x)
+    }
+}
+
+impl Element for Checked {
+    const IS_INTEGER: bool = true;
+    const TOLERANCE: f64 = 0.0;
+    const BYTES: usize = 4;
+
+    fn from_i32(x: i32) -> Checked {
+        Checked {
+            value: x,
+            overflow: false,
+        }
+    }
+
+    fn from_f64(x: f64) -> Checked {
+        Checked {
+            value: x as i32,
+            overflow: false,
+        }
+    }
+
+    fn to_f64(self) -> f64 {
+        self.value as f64
+    }
+
+    fn to_le(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.value.to_le_bytes());
+    }
+
+    fn from_le(bytes: &[u8]) -> Checked {
+        Checked::from_i32(i32::from_le_bytes(bytes[..4].try_into().unwrap()))
+    }
+
+    fn overflowed(self) -> bool {
+        self.overflow
+    }
+}
+
+// Largest error relative to the magnitude of the reference result
+pub fn max_rel_error<T: Element, R: Element>(result: &[T], reference: &[R]) -> f64 {
+    let scale = reference
+        .iter()
+        .map(|r| r.to_f64().abs())
+        .fold(1.0, f64::max);
+
+    result
+        .iter()
+        .zip(reference.iter())
+        .map(|(x, r)| (x.to_f64() - r.to_f64()).abs() / scale)
+        .fold(0.0, f64::max)
+}
+
+// Checks a result against the reference one, exactly for integer types
+pub fn assert_matches<T: Element>(name: &str, result: &[T], reference: &[T]) {
+    if T::IS_INTEGER {
+        assert!(result == reference, "{} differs from serial", name);
+    } else {
+        let error = max_rel_error(result, reference);
+        assert!(
+            error <= T::TOLERANCE,
+            "{} differs from serial by {:e}",
+            name,
+            error
+        );
+    }
+}
+
+// Reports how many elements of a result overflowed in checked mode
+pub fn report_overflow<T: Element>(name: &str, result: &[T]) {
+    let overflowed = result.iter().filter(|x| x.overflowed()).count();
+    if overflowed > 0 {
+        println!(
+            "!!!! \t\t({}: {} of {} elements overflowed)",
+            name,
+            overflowed,
+            result.len()
+        );
+    }
+}
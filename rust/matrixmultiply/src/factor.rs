@@ -0,0 +1,398 @@
+use crate::blocked::{self, BlockSizes};
+use crate::element::Element;
+use crate::{print_speedup, random, MatMulConfig};
+use num::Float;
+use rand::distributions::Uniform;
+use rand::rngs::StdRng;
+use rand::Rng;
+use rayon::prelude::*;
+use std::time::{Duration, Instant};
+
+/*************************************
+ * Blocked LU and Cholesky
+ *************************************/
+// Both factorisations are right-looking: every step factors a panel of `nb`
+// columns, solves the block row (LU) or block column (Cholesky) next to it
+// and subtracts the product of the two from the trailing matrix with the
+// blocked GEMM. The trailing matrix shrinks at every step, and with it the
+// parallel work, while the panel in front of it is mostly serial. The
+// matrices are square, row-major and factored in place.
+
+// Runs `f` on every row of a row-major block, with the index of the row
+fn for_rows<P, F>(parallel: bool, rows: &mut [P], n: usize, f: F)
+where
+    P: Send,
+    F: Fn((usize, &mut [P])) + Send + Sync,
+{
+    if parallel {
+        rows.par_chunks_mut(n).enumerate().for_each(f);
+    } else {
+        rows.chunks_mut(n).enumerate().for_each(f);
+    }
+}
+
+fn swap_rows<P>(a: &mut [P], n: usize, i: usize, j: usize) {
+    let (i, j) = (std::cmp::min(i, j), std::cmp::max(i, j));
+    let (head, tail) = a.split_at_mut(j * n);
+    head[(i * n)..((i + 1) * n)].swap_with_slice(&mut tail[..n]);
+}
+
+// Copies rows `rows` x columns `cols` of a row-major matrix
+fn copy_block<P: Copy>(
+    a: &[P],
+    n: usize,
+    rows: std::ops::Range<usize>,
+    cols: std::ops::Range<usize>,
+) -> Vec<P> {
+    rows.flat_map(|row| {
+        a[RM!(row, cols.start, n)..RM!(row, cols.end, n)]
+            .iter()
+            .copied()
+    })
+    .collect()
+}
+
+// A[end.., end..] -= X (n2 x jb) * Y (jb x n2), with the blocked GEMM
+#[allow(clippy::too_many_arguments)]
+fn trailing_update<P: Element>(
+    parallel: bool,
+    blocks: &BlockSizes,
+    n: usize,
+    end: usize,
+    jb: usize,
+    x: &[P],
+    y: &[P],
+    a: &mut [P],
+) {
+    let n2 = n - end;
+    let mut product = vec![P::default(); n2 * n2];
+    if parallel {
+        blocked::matmul_blocked_par(n2, jb, n2, blocks, x, y, &mut product[..]);
+    } else {
+        blocked::matmul_blocked_serial(n2, jb, n2, blocks, x, y, &mut product[..]);
+    }
+
+    let product = &product[..];
+    for_rows(parallel, &mut a[(end * n)..], n, |(r, row)| {
+        row[end..]
+            .iter_mut()
+            .zip(product[(r * n2)..((r + 1) * n2)].iter())
+            .for_each(|(a, p)| *a -= *p);
+    });
+}
+
+// P A = L U with L unit lower triangular, both stored in A, and row j swapped
+// with row piv[j] at step j.
+pub fn lu<P: Element + Float>(
+    parallel: bool,
+    nb: usize,
+    blocks: &BlockSizes,
+    n: usize,
+    a: &mut [P],
+    piv: &mut [usize],
+) {
+    for j0 in (0..n).step_by(nb) {
+        let end = std::cmp::min(j0 + nb, n);
+
+        // Unblocked LU of the panel, swapping whole rows
+        for j in j0..end {
+            let pivot_row = (j..n)
+                .max_by(|&x, &y| {
+                    let (x, y) = (a[RM!(x, j, n)].abs(), a[RM!(y, j, n)].abs());
+                    x.partial_cmp(&y).unwrap()
+                })
+                .unwrap();
+            piv[j] = pivot_row;
+            if pivot_row != j {
+                swap_rows(a, n, j, pivot_row);
+            }
+
+            let (head, tail) = a.split_at_mut((j + 1) * n);
+            let pivot_row = &head[(j * n)..];
+            let pivot = pivot_row[j];
+            assert!(pivot != P::zero(), "the matrix is singular");
+
+            for_rows(parallel, tail, n, |(_, row)| {
+                let l = row[j] / pivot;
+                row[j] = l;
+                for col in (j + 1)..end {
+                    row[col] -= l * pivot_row[col];
+                }
+            });
+        }
+
+        if end < n {
+            // U12 = L11^-1 A12, row after row
+            for i in (j0 + 1)..end {
+                let (head, tail) = a.split_at_mut(i * n);
+                let row_i = &mut tail[..n];
+                for r in j0..i {
+                    let l = row_i[r];
+                    let row_r = &head[(r * n)..((r + 1) * n)];
+                    row_i[end..]
+                        .iter_mut()
+                        .zip(row_r[end..].iter())
+                        .for_each(|(x, u)| *x -= l * *u);
+                }
+            }
+
+            let l21 = copy_block(a, n, end..n, j0..end);
+            let u12 = copy_block(a, n, j0..end, end..n);
+            trailing_update(parallel, blocks, n, end, end - j0, &l21[..], &u12[..], a);
+        }
+    }
+}
+
+// A = L L^T with L lower triangular, stored in A with the upper part zeroed
+pub fn cholesky<P: Element + Float>(
+    parallel: bool,
+    nb: usize,
+    blocks: &BlockSizes,
+    n: usize,
+    a: &mut [P],
+) {
+    for j0 in (0..n).step_by(nb) {
+        let end = std::cmp::min(j0 + nb, n);
+        let jb = end - j0;
+
+        // Unblocked Cholesky of the diagonal block
+        for j in j0..end {
+            let mut d = a[RM!(j, j, n)];
+            for r in j0..j {
+                d -= a[RM!(j, r, n)] * a[RM!(j, r, n)];
+            }
+            assert!(d > P::zero(), "the matrix is not positive definite");
+            let d = d.sqrt();
+            a[RM!(j, j, n)] = d;
+
+            for i in (j + 1)..end {
+                let mut x = a[RM!(i, j, n)];
+                for r in j0..j {
+                    x -= a[RM!(i, r, n)] * a[RM!(j, r, n)];
+                }
+                a[RM!(i, j, n)] = x / d;
+            }
+        }
+
+        if end < n {
+            // L21 = A21 L11^-T, every row on its own
+            let l11 = copy_block(a, n, j0..end, j0..end);
+            let l11 = &l11[..];
+            for_rows(parallel, &mut a[(end * n)..], n, |(_, row)| {
+                for c in 0..jb {
+                    let mut x = row[j0 + c];
+                    for r in 0..c {
+                        x -= row[j0 + r] * l11[RM!(c, r, jb)];
+                    }
+                    row[j0 + c] = x / l11[RM!(c, c, jb)];
+                }
+            });
+
+            let l21 = copy_block(a, n, end..n, j0..end);
+            let l21_t: Vec<P> = (0..jb)
+                .flat_map(|c| l21.iter().skip(c).step_by(jb).copied())
+                .collect();
+            trailing_update(parallel, blocks, n, end, jb, &l21[..], &l21_t[..], a);
+        }
+    }
+
+    for_rows(parallel, a, n, |(i, row)| {
+        row[(i + 1)..].iter_mut().for_each(|x| *x = P::zero());
+    });
+}
+
+// ||X - Y||_F / ||X||_F
+fn relative_residual<P: Element>(x: &[P], y: &[P]) -> f64 {
+    let (diff, norm) = x
+        .par_iter()
+        .zip(y.par_iter())
+        .map(|(x, y)| {
+            let (x, y) = (Element::to_f64(*x), Element::to_f64(*y));
+            ((x - y) * (x - y), x * x)
+        })
+        .reduce(|| (0.0, 0.0), |a, b| (a.0 + b.0, a.1 + b.1));
+    (diff / norm).sqrt()
+}
+
+// ||P A - L U|| / ||A|| from the factors stored in `lu`
+pub fn lu_residual<P: Element + Float>(
+    blocks: &BlockSizes,
+    n: usize,
+    m_a: &[P],
+    lu: &[P],
+    piv: &[usize],
+) -> f64 {
+    let mut l = vec![P::zero(); n * n];
+    let mut u = vec![P::zero(); n * n];
+    l.par_chunks_mut(n)
+        .zip(u.par_chunks_mut(n))
+        .zip(lu.par_chunks(n))
+        .enumerate()
+        .for_each(|(i, ((l, u), lu))| {
+            l[..i].copy_from_slice(&lu[..i]);
+            l[i] = P::one();
+            u[i..].copy_from_slice(&lu[i..]);
+        });
+
+    let mut product = vec![P::zero(); n * n];
+    blocked::matmul_blocked_par(n, n, n, blocks, &l[..], &u[..], &mut product[..]);
+
+    let mut permuted = m_a.to_vec();
+    for (j, &p) in piv.iter().enumerate() {
+        if p != j {
+            swap_rows(&mut permuted[..], n, j, p);
+        }
+    }
+
+    relative_residual(&permuted[..], &product[..])
+}
+
+// ||A - L L^T|| / ||A|| from the factor stored in `l`
+pub fn cholesky_residual<P: Element + Float>(
+    blocks: &BlockSizes,
+    n: usize,
+    m_a: &[P],
+    l: &[P],
+) -> f64 {
+    let l_t: Vec<P> = (0..n)
+        .flat_map(|c| l.iter().skip(c).step_by(n).copied())
+        .collect();
+
+    let mut product = vec![P::zero(); n * n];
+    blocked::matmul_blocked_par(n, n, n, blocks, l, &l_t[..], &mut product[..]);
+
+    relative_residual(m_a, &product[..])
+}
+
+// Uniform in -1..1 with no structure, nonsingular with probability one, so
+// that the partial pivoting of the LU has rows to swap
+fn general_matrix<P: Element + Float>(rng: &mut StdRng, n: usize) -> Vec<P> {
+    rng.sample_iter(&Uniform::new(-1.0, 1.0))
+        .take(n * n)
+        .map(<P as Element>::from_f64)
+        .collect()
+}
+
+// Symmetric and strictly diagonally dominant, hence positive definite
+fn spd_matrix<P: Element + Float>(rng: &mut StdRng, n: usize) -> Vec<P> {
+    let r: Vec<P> = random::random_matrix(rng, n * n);
+    let diagonal = P::from_i32(20 * n as i32 + 1);
+
+    (0..(n * n))
+        .map(|index| {
+            let (i, j) = (index / n, index % n);
+            let x = r[RM!(i, j, n)] + r[RM!(j, i, n)];
+            if i == j {
+                x + diagonal
+            } else {
+                x
+            }
+        })
+        .collect()
+}
+
+// Times a factorisation of a fresh copy of `m_a` over the runs, returning the
+// best time and the factors of the last run
+fn time_factor<P, F>(name: &str, num_runs: u32, m_a: &[P], mut factor: F) -> (Duration, Vec<P>)
+where
+    P: Element,
+    F: FnMut(&mut [P]),
+{
+    let mut best_time = Duration::new(100, 0);
+    let mut factors = m_a.to_vec();
+
+    for _ in 0..num_runs {
+        factors.copy_from_slice(m_a);
+        let start = Instant::now();
+        factor(&mut factors[..]);
+        let end = Instant::now();
+
+        best_time = std::cmp::min(best_time, end.duration_since(start));
+    }
+
+    println!(
+        "[matmul-rust {}]: \t[{:?}] ms",
+        name,
+        best_time.as_micros() as f64 / 1000_f64
+    );
+
+    (best_time, factors)
+}
+
+fn print_factor_rate(flops: f64, time: Duration, residual: f64) {
+    println!(
+        "++++ \t\t({:.2} GFLOPS, residual {:.2e})",
+        flops / time.as_secs_f64() / 1e9,
+        residual
+    );
+}
+
+// LU of a random m x m matrix and Cholesky of a random SPD one
+pub fn do_factor_runs<P: Element + Float>(matmul_config: &MatMulConfig, rng: &mut StdRng) {
+    let num_runs = matmul_config.num_of_runs;
+    let n = matmul_config.m as usize;
+    let nb = matmul_config.nb as usize;
+    let blocks = &matmul_config.blocks;
+
+    println!(
+        "Factorising {}x{} matrices in panels of {} columns",
+        n, n, nb
+    );
+
+    let m_a: Vec<P> = general_matrix(rng, n);
+    let m_spd: Vec<P> = spd_matrix(rng, n);
+    let lu_flops = 2.0 / 3.0 * (n as f64).powi(3);
+    let cholesky_flops = 1.0 / 3.0 * (n as f64).powi(3);
+
+    let run_lu = |parallel: bool, name: &str| {
+        let mut piv = vec![0; n];
+        let (time, factors) = time_factor(name, num_runs, &m_a[..], |a| {
+            lu(parallel, nb, blocks, n, a, &mut piv[..])
+        });
+        let residual = lu_residual(blocks, n, &m_a[..], &factors[..], &piv[..]);
+        print_factor_rate(lu_flops, time, residual);
+        assert!(
+            residual <= P::TOLERANCE,
+            "{} residual is {:e}",
+            name,
+            residual
+        );
+        time
+    };
+
+    let run_cholesky = |parallel: bool, name: &str| {
+        let (time, factors) = time_factor(name, num_runs, &m_spd[..], |a| {
+            cholesky(parallel, nb, blocks, n, a)
+        });
+        let residual = cholesky_residual(blocks, n, &m_spd[..], &factors[..]);
+        print_factor_rate(cholesky_flops, time, residual);
+        assert!(
+            residual <= P::TOLERANCE,
+            "{} residual is {:e}",
+            name,
+            residual
+        );
+        time
+    };
+
+    let mut lu_serial_time = Duration::new(100, 0);
+    let mut cholesky_serial_time = Duration::new(100, 0);
+
+    if (matmul_config.code_config == 0) || (matmul_config.code_config == 2) {
+        lu_serial_time = run_lu(false, "lu_serial");
+        cholesky_serial_time = run_cholesky(false, "cholesky_serial");
+        println!();
+    }
+
+    if (matmul_config.code_config == 0) || (matmul_config.code_config == 1) {
+        let lu_time = run_lu(true, "lu_par");
+        if matmul_config.code_config == 0 {
+            print_speedup(matmul_config, lu_serial_time, lu_time);
+        }
+
+        let cholesky_time = run_cholesky(true, "cholesky_par");
+        if matmul_config.code_config == 0 {
+            print_speedup(matmul_config, cholesky_serial_time, cholesky_time);
+        }
+    }
+}
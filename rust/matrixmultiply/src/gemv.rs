@@ -0,0 +1,86 @@
+use crate::element::{self, Element};
+use crate::{print_speedup, random, roofline, time_kernel, MatMulConfig};
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use std::time::Duration;
+
+// A kernel called with (k, A, x, y)
+type GemvKernel<T> = fn(usize, &[T], &[T], &mut [T]);
+
+// Rows of A per task of the chunked kernel
+const GEMV_CHUNK: usize = 64;
+
+/*************************************
+ * Matrix-vector multiply
+ *************************************/
+// y = A x does one multiply-add per element of A, which it reads once, so it
+// is bound by memory and the cost of a task is not hidden behind much work.
+// One task per row is what `matmul_par_row` does for the product of two
+// matrices; the chunked kernel gives every task GEMV_CHUNK rows instead.
+
+fn dot<T: Element>(x: &[T], y: &[T]) -> T {
+    x.iter().zip(y.iter()).map(|(a, b)| *a * *b).sum()
+}
+
+// y (m) = A (m x k) x (k), A row-major
+pub fn gemv_serial<T: Element>(k: usize, m_a: &[T], x: &[T], y: &mut [T]) {
+    y.iter_mut()
+        .zip(m_a.chunks(k))
+        .for_each(|(y, a_row)| *y = dot(a_row, x));
+}
+
+pub fn gemv_par_row<T: Element>(k: usize, m_a: &[T], x: &[T], y: &mut [T]) {
+    y.par_iter_mut()
+        .zip(m_a.par_chunks(k))
+        .for_each(|(y, a_row)| *y = dot(a_row, x));
+}
+
+pub fn gemv_par_chunked<T: Element>(k: usize, m_a: &[T], x: &[T], y: &mut [T]) {
+    y.par_chunks_mut(GEMV_CHUNK)
+        .zip(m_a.par_chunks(GEMV_CHUNK * k))
+        .for_each(|(y, a_rows)| gemv_serial(k, a_rows, x, y));
+}
+
+// y = A x with a random x
+pub fn do_gemv_runs<T: Element>(matmul_config: &MatMulConfig, m_a: &[T], rng: &mut StdRng) {
+    let num_runs = matmul_config.num_of_runs;
+    let (m, k, _) = matmul_config.dims();
+
+    let roofline = if matmul_config.roofline {
+        Some(roofline::measure(num_runs))
+    } else {
+        None
+    };
+
+    let x: Vec<T> = random::random_matrix(rng, k);
+    let mut serial_y = vec![T::default(); m];
+    let mut serial_time = Duration::new(100, 0);
+
+    if (matmul_config.code_config == 0) || (matmul_config.code_config == 2) {
+        serial_time = time_kernel("gemv_serial", num_runs, || {
+            gemv_serial(k, m_a, &x[..], &mut serial_y[..])
+        });
+        roofline::print_rates::<T>((m, k, 1), serial_time, roofline.as_ref());
+        element::report_overflow("gemv_serial", &serial_y[..]);
+        println!();
+    }
+
+    if (matmul_config.code_config == 0) || (matmul_config.code_config == 1) {
+        let kernels: [(&str, GemvKernel<T>); 2] = [
+            ("gemv_par_row", gemv_par_row),
+            ("gemv_par_chunked", gemv_par_chunked),
+        ];
+
+        for (name, kernel) in kernels.iter() {
+            let mut y = vec![T::default(); m];
+            let par_time = time_kernel(name, num_runs, || kernel(k, m_a, &x[..], &mut y[..]));
+            roofline::print_rates::<T>((m, k, 1), par_time, roofline.as_ref());
+            element::report_overflow(name, &y[..]);
+
+            if matmul_config.code_config == 0 {
+                element::assert_matches(name, &y[..], &serial_y[..]);
+                print_speedup(matmul_config, serial_time, par_time);
+            }
+        }
+    }
+}
@@ -5,11 +5,14 @@ extern crate num_cpus;
 extern crate rayon;
 
 use clap::{App, Arg};
-use rand::{thread_rng, Rng};
+use element::{Checked, Element};
+use rand::rngs::StdRng;
+use rand::{thread_rng, Rng, SeedableRng};
 use rayon::prelude::*;
+use std::num::Wrapping;
 use std::time::{Duration, Instant};
 
-const MULT_CHUNK: usize = 1 * 1024;
+const MULT_CHUNK: usize = 1024;
 const LINEAR_CHUNK: usize = 64 * 1024;
 
 macro_rules! RM {
@@ -18,114 +21,598 @@ macro_rules! RM {
     };
 }
 
+mod batched;
+mod blocked;
+mod element;
+mod factor;
+mod gemv;
+mod matio;
+mod packed;
+mod random;
+mod roofline;
+mod sparse;
+mod strassen;
+mod transposed;
+mod zorder;
+
 fn main() {
     let matmul_config = parse_arguments();
 
-    let mut rng = thread_rng();
-
-    let num_mat_elements = matmul_config.size * matmul_config.size;
-
-    let mut m_a: Vec<i32> = vec![1; num_mat_elements as usize];
-
-    let mut m_b: Vec<i32> = vec![1; num_mat_elements as usize];
-
     // Set the number of threads for rayon
     rayon::ThreadPoolBuilder::new()
         .num_threads(matmul_config.num_threads as usize)
         .build_global()
         .unwrap();
 
+    // The factorisations need floating point, integer types use f64
+    if matmul_config.factor {
+        let mut rng = StdRng::seed_from_u64(matmul_config.seed);
+        match matmul_config.dtype {
+            DType::F32 => factor::do_factor_runs::<f32>(&matmul_config, &mut rng),
+            DType::F64 => factor::do_factor_runs::<f64>(&matmul_config, &mut rng),
+            dtype => {
+                println!("{:?} matrices are factored in f64", dtype);
+                factor::do_factor_runs::<f64>(&matmul_config, &mut rng)
+            }
+        }
+        return;
+    }
+
+    match matmul_config.dtype {
+        DType::I32 => run::<i32>(&matmul_config),
+        DType::I64 => run::<i64>(&matmul_config),
+        DType::F32 => run::<f32>(&matmul_config),
+        DType::F64 => run::<f64>(&matmul_config),
+        DType::Wrapping => run::<Wrapping<i32>>(&matmul_config),
+        DType::Checked => run::<Checked>(&matmul_config),
+    }
+}
+
+fn run<T: Element>(matmul_config: &MatMulConfig) {
+    let mut rng = StdRng::seed_from_u64(matmul_config.seed);
+
+    if matmul_config.sparse {
+        run_sparse::<T>(matmul_config, &mut rng);
+        return;
+    }
+    if matmul_config.batch > 0 {
+        batched::do_batched_runs::<T>(matmul_config, &mut rng);
+        return;
+    }
+
+    // Matrices read from files set the dimensions
+    let mut matmul_config = matmul_config.clone();
+
+    let mut m_a: Vec<T> = match &matmul_config.input_a {
+        Some(path) => {
+            let a = load_input::<T>(path);
+            matmul_config.m = a.rows as u64;
+            matmul_config.k = a.cols as u64;
+            a.data
+        }
+        None => random::random_matrix(&mut rng, (matmul_config.m * matmul_config.k) as usize),
+    };
+
+    if matmul_config.gemv {
+        gemv::do_gemv_runs(&matmul_config, &m_a[..], &mut rng);
+        return;
+    }
+
+    let mut m_b: Vec<T> = match &matmul_config.input_b {
+        Some(path) => {
+            let b = load_input::<T>(path);
+            assert_eq!(
+                b.rows as u64, matmul_config.k,
+                "B must have as many rows as A has columns"
+            );
+            matmul_config.n = b.cols as u64;
+            b.data
+        }
+        None => random::random_matrix(&mut rng, (matmul_config.k * matmul_config.n) as usize),
+    };
+
     do_runs(&matmul_config, &mut m_a, &mut m_b);
 }
 
+// A is sparse: a Matrix Market file, the non-zeros of a binary one, or an m x k
+// matrix with power-law rows
+fn run_sparse<T: Element>(matmul_config: &MatMulConfig, rng: &mut StdRng) {
+    let (m, k, n) = matmul_config.dims();
+
+    let m_a = match &matmul_config.input_a {
+        Some(path) if matio::is_mtx(path) => {
+            let mtx = matio::read_mtx_entries::<T>(path)
+                .unwrap_or_else(|err| panic!("Could not read {}: {}", path, err));
+            println!("Loaded {}x{} matrix from {}", mtx.rows, mtx.cols, path);
+            sparse::Csr::from_entries(mtx)
+        }
+        Some(path) => {
+            let a = load_input::<T>(path);
+            sparse::Csr::from_dense(a.rows, a.cols, &a.data[..])
+        }
+        None => sparse::Csr::power_law(m, k),
+    };
+
+    let (m_b, n): (Vec<T>, usize) = match &matmul_config.input_b {
+        Some(path) => {
+            let b = load_input::<T>(path);
+            assert_eq!(
+                b.rows, m_a.cols,
+                "B must have as many rows as A has columns"
+            );
+            (b.data, b.cols)
+        }
+        None => (random::random_matrix(rng, m_a.cols * n), n),
+    };
+
+    sparse::do_sparse_runs(matmul_config, &m_a, &m_b[..], n);
+}
+
+fn load_input<T: Element>(path: &str) -> matio::Matrix<T> {
+    let matrix =
+        matio::load::<T>(path).unwrap_or_else(|err| panic!("Could not read {}: {}", path, err));
+    println!(
+        "Loaded {}x{} matrix from {}",
+        matrix.rows, matrix.cols, path
+    );
+    matrix
+}
+
+// Writes the result of a kernel to the --output file
+fn save_result<T: Element>(path: &str, name: &str, m: usize, n: usize, m_c: &[T]) {
+    match matio::save(path, m, n, m_c) {
+        Ok(()) => println!("++++ \t\t(wrote the {} result to {})", name, path),
+        Err(err) => println!("Could not write the {} result to {}: {}", name, path, err),
+    }
+}
+
+// Element type of the matrices, --dtype
+#[derive(Copy, Clone, Debug)]
+pub enum DType {
+    I32,
+    I64,
+    F32,
+    F64,
+    Wrapping,
+    Checked,
+}
+
 // Configuration file, reflects command line options
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct MatMulConfig {
-    pub size: u64,
+    pub m: u64,
+    pub k: u64,
+    pub n: u64,
     pub num_threads: u32,
     pub num_of_runs: u32,
     pub code_config: u32,
+    pub cutoff: u64,
+    pub blocks: blocked::BlockSizes,
+    pub tune: bool,
+    pub tune_file: Option<String>,
+    pub dtype: DType,
+    pub roofline: bool,
+    pub sparse: bool,
+    pub gemv: bool,
+    pub factor: bool,
+    pub nb: u64,
+    pub batch: u64,
+    pub small: u64,
+    pub input_a: Option<String>,
+    pub input_b: Option<String>,
+    pub output: Option<String>,
+    pub seed: u64,
+    pub freivalds: u32,
 }
 
-pub fn do_runs(matmul_config: &MatMulConfig, m_a: &mut [i32], m_b: &mut [i32]) {
+impl MatMulConfig {
+    // C is m x n, A is m x k and B is k x n
+    pub fn dims(&self) -> (usize, usize, usize) {
+        (self.m as usize, self.k as usize, self.n as usize)
+    }
+}
+
+// A multiply working on the padded Z-order layout
+type ZOrderKernel<'a, T> = dyn Fn(&[T], &[T], &mut [T]) + 'a;
+
+// A multiply of A by B^T, the bool being whether it produces C^T
+type TransposedKernel<'a, T> = (&'a str, bool, fn(usize, usize, usize, &[T], &[T], &mut [T]));
+
+pub fn do_runs<T: Element>(matmul_config: &MatMulConfig, m_a: &mut [T], m_b: &mut [T]) {
     let num_runs = matmul_config.num_of_runs;
 
     let mut serial_time = Duration::new(100, 0);
-    let mut row_parallel_time = Duration::new(100, 0);
-    let mut quad_parallel_time = Duration::new(100, 0);
 
-    let num_mat_elements = matmul_config.size * matmul_config.size;
+    let (m, k, n) = matmul_config.dims();
+
+    let roofline = if matmul_config.roofline {
+        Some(roofline::measure(num_runs))
+    } else {
+        None
+    };
 
-    let mut serial_m_c: Vec<i32> = vec![0; num_mat_elements as usize];
+    let mut serial_m_c: Vec<T> = vec![T::default(); m * n];
 
     if (matmul_config.code_config == 0) || (matmul_config.code_config == 2) {
-        for _ in 0..num_runs {
+        serial_time = time_kernel("serial", num_runs, || {
+            matmul_serial(m, k, n, m_a, m_b, &mut serial_m_c[..]);
+            // matmul_seq(m, k, n, m_a, m_b, &mut serial_m_c[..]);
+        });
+        roofline::print_rates::<T>((m, k, n), serial_time, roofline.as_ref());
+        element::report_overflow("serial", &serial_m_c[..]);
+        if let Some(path) = &matmul_config.output {
+            save_result(path, "serial", m, n, &serial_m_c[..]);
+        }
+
+        let mut blocked_m_c: Vec<T> = vec![T::default(); m * n];
+        let blocked_serial_time = time_kernel("serial_blocked", num_runs, || {
+            blocked::matmul_blocked_serial(
+                m,
+                k,
+                n,
+                &matmul_config.blocks,
+                m_a,
+                m_b,
+                &mut blocked_m_c[..],
+            )
+        });
+        element::assert_matches("serial_blocked", &blocked_m_c[..], &serial_m_c[..]);
+        roofline::print_rates::<T>((m, k, n), blocked_serial_time, roofline.as_ref());
+        element::report_overflow("serial_blocked", &blocked_m_c[..]);
+    }
+
+    if (matmul_config.code_config == 0) || (matmul_config.code_config == 1) {
+        let mut par_row_m_c: Vec<T> = vec![T::default(); m * n];
+        let row_parallel_time = time_kernel("par_row", num_runs, || {
+            matmul_par_row(m, k, n, m_a, m_b, &mut par_row_m_c[..])
+        });
+        roofline::print_rates::<T>((m, k, n), row_parallel_time, roofline.as_ref());
+        element::report_overflow("par_row", &par_row_m_c[..]);
+        random::check_freivalds(matmul_config, "par_row", m_a, m_b, &par_row_m_c[..]);
+        if let (Some(path), 1) = (&matmul_config.output, matmul_config.code_config) {
+            save_result(path, "par_row", m, n, &par_row_m_c[..]);
+        }
+
+        if matmul_config.code_config == 0 {
+            element::assert_matches("par_row", &par_row_m_c[..], &serial_m_c[..]);
+            print_speedup(matmul_config, serial_time, row_parallel_time);
+        }
+
+        /////////////////////////////////////////////
+        // Dot products on a transposed B, partitioned by rows, columns and blocks
+        let transposed_kernels: [TransposedKernel<T>; 3] = [
+            ("par_row_bt", false, transposed::matmul_par_row_bt),
+            ("par_col_bt", true, transposed::matmul_par_col),
+            ("par_2d_bt", false, transposed::matmul_par_2d),
+        ];
+
+        for (name, transposed_result, kernel) in transposed_kernels.iter() {
+            let mut par_bt_m_c: Vec<T> = vec![T::default(); m * n];
+            let (bt_parallel_time, transpose_time) = time_transposed(
+                matmul_config,
+                m_a,
+                m_b,
+                &mut par_bt_m_c[..],
+                *transposed_result,
+                kernel,
+            );
+
+            println!(
+                "[matmul-rust {}]: \t[{:?}] ms",
+                name,
+                bt_parallel_time.as_micros() as f64 / 1000_f64
+            );
+            roofline::print_rates::<T>((m, k, n), bt_parallel_time, roofline.as_ref());
+            println!(
+                "++++ \t\t({:?} ms transposing)",
+                transpose_time.as_micros() as f64 / 1000_f64
+            );
+            element::report_overflow(name, &par_bt_m_c[..]);
+            random::check_freivalds(matmul_config, name, m_a, m_b, &par_bt_m_c[..]);
+
+            if matmul_config.code_config == 0 {
+                element::assert_matches(name, &par_bt_m_c[..], &serial_m_c[..]);
+                print_speedup(matmul_config, serial_time, bt_parallel_time);
+            }
+        }
+
+        /////////////////////////////////////////////
+        // Cache-blocked multiply over output tiles
+        let blocks = if matmul_config.tune {
+            blocked::autotune(
+                m,
+                k,
+                n,
+                num_runs,
+                matmul_config.tune_file.as_deref(),
+                m_a,
+                m_b,
+            )
+        } else {
+            matmul_config.blocks
+        };
+
+        let mut par_blocked_m_c: Vec<T> = vec![T::default(); m * n];
+        let blocked_parallel_time = time_kernel("par_blocked", num_runs, || {
+            blocked::matmul_blocked_par(m, k, n, &blocks, m_a, m_b, &mut par_blocked_m_c[..])
+        });
+        roofline::print_rates::<T>((m, k, n), blocked_parallel_time, roofline.as_ref());
+        element::report_overflow("par_blocked", &par_blocked_m_c[..]);
+        random::check_freivalds(matmul_config, "par_blocked", m_a, m_b, &par_blocked_m_c[..]);
+
+        if matmul_config.code_config == 0 {
+            element::assert_matches("par_blocked", &par_blocked_m_c[..], &serial_m_c[..]);
+            print_speedup(matmul_config, serial_time, blocked_parallel_time);
+        }
+
+        /////////////////////////////////////////////
+        // Packed floating point GEMM
+        run_packed::<T, f32>(
+            "packed_f32",
+            matmul_config,
+            m_a,
+            m_b,
+            &serial_m_c[..],
+            serial_time,
+            roofline.as_ref(),
+        );
+        run_packed::<T, f64>(
+            "packed_f64",
+            matmul_config,
+            m_a,
+            m_b,
+            &serial_m_c[..],
+            serial_time,
+            roofline.as_ref(),
+        );
+
+        /////////////////////////////////////////////
+        // Recursive multiplies in Z-order, including the layout conversion
+        let cutoff = matmul_config.cutoff as usize;
+        let (pm, pk, pn) = (
+            zorder::zorder_size(m),
+            zorder::zorder_size(k),
+            zorder::zorder_size(n),
+        );
+        let zorder_kernels: [(&str, &ZOrderKernel<T>); 3] = [
+            ("par_z", &|a, b, dest| matmulz(pm, pk, pn, a, b, dest)),
+            ("strassen", &|a, b, dest| {
+                strassen::strassen(cutoff, pm, pk, pn, a, b, dest)
+            }),
+            ("winograd", &|a, b, dest| {
+                strassen::winograd(cutoff, pm, pk, pn, a, b, dest)
+            }),
+        ];
+
+        for (name, kernel) in zorder_kernels.iter() {
+            let mut par_quad_m_c: Vec<T> = vec![T::default(); m * n];
+            let (quad_parallel_time, convert_time) =
+                time_zorder(matmul_config, m_a, m_b, &mut par_quad_m_c[..], kernel);
+
+            println!(
+                "[matmul-rust {}]: \t[{:?}] ms",
+                name,
+                quad_parallel_time.as_micros() as f64 / 1000_f64
+            );
+            roofline::print_rates::<T>((m, k, n), quad_parallel_time, roofline.as_ref());
+            println!(
+                "++++ \t\t({:?} ms layout conversion, padded to {}x{}x{})",
+                convert_time.as_micros() as f64 / 1000_f64,
+                pm,
+                pk,
+                pn
+            );
+            element::report_overflow(name, &par_quad_m_c[..]);
+            random::check_freivalds(matmul_config, name, m_a, m_b, &par_quad_m_c[..]);
+
+            if matmul_config.code_config == 0 {
+                element::assert_matches(name, &par_quad_m_c[..], &serial_m_c[..]);
+                print_speedup(matmul_config, serial_time, quad_parallel_time);
+            }
+        }
+    }
+}
+
+// Times the packed GEMM on floating point copies of the inputs, checking it
+// against the integer serial result when that was computed.
+pub fn run_packed<T, P>(
+    name: &str,
+    matmul_config: &MatMulConfig,
+    m_a: &[T],
+    m_b: &[T],
+    serial_m_c: &[T],
+    serial_time: Duration,
+    roofline: Option<&roofline::Roofline>,
+) where
+    T: Element,
+    P: packed::PackedElement + Element + num::NumCast,
+{
+    let (m, k, n) = matmul_config.dims();
+    let f_a: Vec<P> = m_a
+        .par_iter()
+        .map(|x| num::cast(x.to_f64()).unwrap())
+        .collect();
+    let f_b: Vec<P> = m_b
+        .par_iter()
+        .map(|x| num::cast(x.to_f64()).unwrap())
+        .collect();
+    let mut f_c: Vec<P> = vec![P::default(); m * n];
+
+    let packed_time = time_kernel(name, matmul_config.num_of_runs, || {
+        packed::gemm_packed_par(m, k, n, &f_a[..], &f_b[..], &mut f_c[..])
+    });
+    roofline::print_rates::<P>((m, k, n), packed_time, roofline);
+    random::check_freivalds(matmul_config, name, &f_a[..], &f_b[..], &f_c[..]);
+
+    if matmul_config.code_config == 0 {
+        // Same kernel on one thread, for the speedup of the algorithm itself
+        let mut packed_serial_time = Duration::new(100, 0);
+        let mut serial_f_c: Vec<P> = vec![P::default(); m * n];
+
+        for _ in 0..matmul_config.num_of_runs {
             let serial_start = Instant::now();
-            matmul_serial(matmul_config.size as usize, m_a, m_b, &mut serial_m_c[..]);
-            // matmul_seq(m_a, m_b, &mut serial_m_c[..]);
+            packed::gemm_packed_serial(m, k, n, &f_a[..], &f_b[..], &mut serial_f_c[..]);
             let serial_end = Instant::now();
 
-            serial_time = std::cmp::min(serial_time, serial_end.duration_since(serial_start));
+            packed_serial_time =
+                std::cmp::min(packed_serial_time, serial_end.duration_since(serial_start));
         }
 
+        // The serial result carries the rounding of T, which is coarser than
+        // that of P for f32. Floating point does not wrap, so a large
+        // difference from an integer result means that it overflowed
+        let tolerance = P::TOLERANCE.max(T::TOLERANCE);
+        let error = element::max_rel_error(&f_c[..], serial_m_c);
+        if error > tolerance && T::IS_INTEGER {
+            println!(
+                "!!!! \t\t({}: differs from the integer result, which overflowed)",
+                name
+            );
+        } else {
+            assert!(
+                error <= tolerance,
+                "{} differs from serial by {:e}",
+                name,
+                error
+            );
+        }
+        println!(
+            "++++ \t\t({:.2}x speedup from {:?} threads, max relative error {:.1e})",
+            serial_time.as_micros() as f64 / packed_time.as_micros() as f64,
+            matmul_config.num_threads,
+            error
+        );
         println!(
-            "[matmul-rust serial]: \t[{:?}] ms",
-            serial_time.as_micros() as f64 / 1000 as f64
+            "++++ \t\t({:.2}x speedup over the serial packed kernel [{:?}] ms)\n",
+            packed_serial_time.as_micros() as f64 / packed_time.as_micros() as f64,
+            packed_serial_time.as_micros() as f64 / 1000_f64
         );
     }
+}
 
-    if (matmul_config.code_config == 0) || (matmul_config.code_config == 1) {
-        let mut par_row_m_c: Vec<i32> = vec![0; num_mat_elements as usize];
+// Times a kernel working on the padded Z-order layout. Returns the best total
+// time, which includes converting the operands and the result, and the best
+// time spent in the conversions alone.
+pub fn time_zorder<T, F>(
+    matmul_config: &MatMulConfig,
+    m_a: &[T],
+    m_b: &[T],
+    m_c: &mut [T],
+    kernel: F,
+) -> (Duration, Duration)
+where
+    T: Element,
+    F: Fn(&[T], &[T], &mut [T]),
+{
+    let (m, k, n) = matmul_config.dims();
+    let (pm, pk, pn) = (
+        zorder::zorder_size(m),
+        zorder::zorder_size(k),
+        zorder::zorder_size(n),
+    );
 
-        for _ in 0..num_runs {
-            let par_start = Instant::now();
-            matmul_par_row(matmul_config.size as usize, m_a, m_b, &mut par_row_m_c[..]);
-            let par_end = Instant::now();
+    let mut z_a: Vec<T> = vec![T::default(); pm * pk];
+    let mut z_b: Vec<T> = vec![T::default(); pk * pn];
+    let mut z_c: Vec<T> = vec![T::default(); pm * pn];
 
-            row_parallel_time = std::cmp::min(row_parallel_time, par_end.duration_since(par_start));
-        }
+    let mut total_time = Duration::new(100, 0);
+    let mut convert_time = Duration::new(100, 0);
 
-        println!(
-            "[matmul-rust par_row]: \t[{:?}] ms",
-            row_parallel_time.as_micros() as f64 / 1000 as f64
+    for _ in 0..matmul_config.num_of_runs {
+        let par_start = Instant::now();
+        zorder::to_zorder(m, k, m_a, &mut z_a[..]);
+        zorder::to_zorder(k, n, m_b, &mut z_b[..]);
+        let mult_start = Instant::now();
+        kernel(&z_a[..], &z_b[..], &mut z_c[..]);
+        let mult_end = Instant::now();
+        zorder::from_zorder(m, n, &z_c[..], m_c);
+        let par_end = Instant::now();
+
+        total_time = std::cmp::min(total_time, par_end.duration_since(par_start));
+        convert_time = std::cmp::min(
+            convert_time,
+            mult_start.duration_since(par_start) + par_end.duration_since(mult_end),
         );
+    }
 
-        if matmul_config.code_config == 0 {
-            assert_eq!(par_row_m_c, serial_m_c);
-            println!(
-                "++++ \t\t({:.2}x speedup from {:?} threads)\n",
-                serial_time.as_micros() as f64 / row_parallel_time.as_micros() as f64,
-                matmul_config.num_threads
-            );
+    (total_time, convert_time)
+}
+
+// Times a kernel multiplying A by a transposed copy of B. Returns the best
+// total time, which includes transposing B and, for kernels producing C^T, the
+// result, and the best time spent in the transposes alone.
+pub fn time_transposed<T, F>(
+    matmul_config: &MatMulConfig,
+    m_a: &[T],
+    m_b: &[T],
+    m_c: &mut [T],
+    transposed_result: bool,
+    kernel: F,
+) -> (Duration, Duration)
+where
+    T: Element,
+    F: Fn(usize, usize, usize, &[T], &[T], &mut [T]),
+{
+    let (m, k, n) = matmul_config.dims();
+
+    let mut m_bt: Vec<T> = vec![T::default(); k * n];
+    let mut m_ct: Vec<T> = vec![T::default(); if transposed_result { m * n } else { 0 }];
+
+    let mut total_time = Duration::new(100, 0);
+    let mut transpose_time = Duration::new(100, 0);
+
+    for _ in 0..matmul_config.num_of_runs {
+        let par_start = Instant::now();
+        transposed::transpose_par(k, n, m_b, &mut m_bt[..]);
+        let mult_start = Instant::now();
+        if transposed_result {
+            kernel(m, k, n, m_a, &m_bt[..], &mut m_ct[..]);
+        } else {
+            kernel(m, k, n, m_a, &m_bt[..], m_c);
         }
+        let mult_end = Instant::now();
+        if transposed_result {
+            transposed::transpose_par(n, m, &m_ct[..], m_c);
+        }
+        let par_end = Instant::now();
 
-        /////////////////////////////////////////////
-        // let mut par_quad_m_c: Vec<i32> = vec![0; num_mat_elements as usize];
-
-        // for _ in 0..num_runs {
-        //     let par_start = Instant::now();
-        //     matmulz(m_a, m_b, &mut par_quad_m_c[..]);
-        //     let par_end = Instant::now();
-
-        //     quad_parallel_time =
-        //         std::cmp::min(quad_parallel_time, par_end.duration_since(par_start));
-        // }
-
-        // assert_eq!(par_quad_m_c, serial_m_c);
-
-        // println!(
-        //     "[matmul-rust par_row]: \t[{:?}] ms",
-        //     quad_parallel_time.as_micros() as f64 / 1000 as f64
-        // );
-
-        // if matmul_config.code_config == 0 {
-        //     println!(
-        //         "++++ \t\t({:.2}x speedup from {:?} threads)\n",
-        //         serial_time.as_micros() as f64 / quad_parallel_time.as_micros() as f64,
-        //         matmul_config.num_threads
-        //     );
-        // }
+        total_time = std::cmp::min(total_time, par_end.duration_since(par_start));
+        transpose_time = std::cmp::min(
+            transpose_time,
+            mult_start.duration_since(par_start) + par_end.duration_since(mult_end),
+        );
     }
+
+    (total_time, transpose_time)
+}
+
+// Times `kernel` over the runs and prints the best time
+pub fn time_kernel<F: FnMut()>(name: &str, num_runs: u32, mut kernel: F) -> Duration {
+    let mut best_time = Duration::new(100, 0);
+
+    for _ in 0..num_runs {
+        let start = Instant::now();
+        kernel();
+        let end = Instant::now();
+
+        best_time = std::cmp::min(best_time, end.duration_since(start));
+    }
+
+    println!(
+        "[matmul-rust {}]: \t[{:?}] ms",
+        name,
+        best_time.as_micros() as f64 / 1000_f64
+    );
+
+    best_time
+}
+
+pub fn print_speedup(matmul_config: &MatMulConfig, serial_time: Duration, time: Duration) {
+    println!(
+        "++++ \t\t({:.2}x speedup from {:?} threads)\n",
+        serial_time.as_micros() as f64 / time.as_micros() as f64,
+        matmul_config.num_threads
+    );
 }
 
 pub fn parse_arguments() -> MatMulConfig {
@@ -140,6 +627,24 @@ pub fn parse_arguments() -> MatMulConfig {
                 .value_name("SIZE")
                 .help("size of the square matrix (default: 1024)"),
         )
+        .arg(
+            Arg::with_name("M")
+                .long("m")
+                .value_name("M")
+                .help("rows of A and C (default: SIZE)"),
+        )
+        .arg(
+            Arg::with_name("K")
+                .long("k")
+                .value_name("K")
+                .help("columns of A and rows of B (default: SIZE)"),
+        )
+        .arg(
+            Arg::with_name("N")
+                .long("n")
+                .value_name("N")
+                .help("columns of B and C (default: SIZE)"),
+        )
         .arg(
             Arg::with_name("NUM_OF_RUNS")
                 .short("r")
@@ -154,6 +659,114 @@ pub fn parse_arguments() -> MatMulConfig {
                 .value_name("NUMBER_OF_THREADS")
                 .help("number of threads to use (default: MAX_CPUS)"),
         )
+        .arg(
+            Arg::with_name("CUTOFF")
+                .long("cutoff")
+                .value_name("CUTOFF")
+                .help("matrix size below which Strassen uses the classic kernel (default: 128)"),
+        )
+        .arg(
+            Arg::with_name("MC")
+                .long("mc")
+                .value_name("MC")
+                .help("rows of the output tiles of the blocked kernel (default: from --tune-file or 64)"),
+        )
+        .arg(Arg::with_name("KC").long("kc").value_name("KC").help(
+            "depth of the shared dimension slabs of the blocked kernel (default: from --tune-file or 256)",
+        ))
+        .arg(
+            Arg::with_name("NC")
+                .long("nc")
+                .value_name("NC")
+                .help("columns of the output tiles of the blocked kernel (default: from --tune-file or 512)"),
+        )
+        .arg(
+            Arg::with_name("TUNE")
+                .long("tune")
+                .help("sweep the tile sizes of the blocked kernel and use the best one, recording it in --tune-file if given"),
+        )
+        .arg(
+            Arg::with_name("TUNE_FILE")
+                .long("tune-file")
+                .value_name("FILE")
+                .help("file the blocked kernel reads its tile sizes from and --tune records them in"),
+        )
+        .arg(
+            Arg::with_name("DTYPE")
+                .long("dtype")
+                .value_name("DTYPE")
+                .possible_values(&["i32", "i64", "f32", "f64", "wrapping", "checked"])
+                .help("element type, wrapping and checked are i32 with explicit overflow (default: i32)"),
+        )
+        .arg(
+            Arg::with_name("INPUT_A")
+                .long("a")
+                .value_name("FILE")
+                .help("read A from a Matrix Market (.mtx) or binary file, setting m and k (default: random from --seed)"),
+        )
+        .arg(
+            Arg::with_name("INPUT_B")
+                .long("b")
+                .value_name("FILE")
+                .help("read B from a Matrix Market (.mtx) or binary file, setting k and n (default: random from --seed)"),
+        )
+        .arg(
+            Arg::with_name("OUTPUT")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("write C to a Matrix Market (.mtx) or binary file"),
+        )
+        .arg(
+            Arg::with_name("SPARSE")
+                .long("sparse")
+                .help("benchmark CSR SpMV and SpMM with a sparse A instead of the dense kernels"),
+        )
+        .arg(
+            Arg::with_name("GEMV")
+                .long("gemv")
+                .help("benchmark the matrix-vector product of A and a random vector of length k"),
+        )
+        .arg(
+            Arg::with_name("FACTOR")
+                .long("factor")
+                .help("benchmark the blocked LU and Cholesky factorisations of m x m matrices"),
+        )
+        .arg(
+            Arg::with_name("NB")
+                .long("nb")
+                .value_name("NB")
+                .help("columns of the panels of the factorisations (default: 64)"),
+        )
+        .arg(
+            Arg::with_name("BATCH")
+                .long("batch")
+                .value_name("COUNT")
+                .help("benchmark a batch of COUNT products of small matrices (default: off)"),
+        )
+        .arg(
+            Arg::with_name("SMALL")
+                .long("small")
+                .value_name("SMALL")
+                .help("size of the square matrices of the batch, 4 to 64 (default: 16)"),
+        )
+        .arg(
+            Arg::with_name("SEED")
+                .long("seed")
+                .value_name("SEED")
+                .help("seed of the random inputs (default: random)"),
+        )
+        .arg(
+            Arg::with_name("FREIVALDS")
+                .long("freivalds")
+                .value_name("TRIALS")
+                .help("check every parallel result with TRIALS rounds of Freivalds' algorithm (default: 0)"),
+        )
+        .arg(
+            Arg::with_name("ROOFLINE")
+                .long("roofline")
+                .help("measure the peak bandwidth and GFLOPS and place every variant on the roofline"),
+        )
         .arg(
             Arg::with_name("CODE")
                 .short("c")
@@ -168,96 +781,183 @@ pub fn parse_arguments() -> MatMulConfig {
 
     // Match and store all values of the arguments
     let size = value_t!(matches.value_of("SIZE"), u64).unwrap_or(1000);
+    let m = value_t!(matches.value_of("M"), u64).unwrap_or(size);
+    let k = value_t!(matches.value_of("K"), u64).unwrap_or(size);
+    let n = value_t!(matches.value_of("N"), u64).unwrap_or(size);
     let num_of_runs = value_t!(matches.value_of("NUM_OF_RUNS"), u32).unwrap_or(1);
     let num_threads =
         value_t!(matches.value_of("NUMBER_OF_THREADS"), u32).unwrap_or(max_threads as u32);
     let code_config = value_t!(matches.value_of("CODE"), u32).unwrap_or(0);
+    let cutoff = value_t!(matches.value_of("CUTOFF"), u64).unwrap_or(128);
+    let tune_file = matches.value_of("TUNE_FILE").map(String::from);
+    let tuned = tune_file
+        .as_deref()
+        .and_then(blocked::BlockSizes::load_tuned)
+        .unwrap_or_else(blocked::BlockSizes::default_sizes);
+    let blocks = blocked::BlockSizes {
+        mc: value_t!(matches.value_of("MC"), usize).unwrap_or(tuned.mc),
+        kc: value_t!(matches.value_of("KC"), usize).unwrap_or(tuned.kc),
+        nc: value_t!(matches.value_of("NC"), usize).unwrap_or(tuned.nc),
+    };
+    let tune = matches.is_present("TUNE");
+    let roofline = matches.is_present("ROOFLINE");
+    let sparse = matches.is_present("SPARSE");
+    let gemv = matches.is_present("GEMV");
+    let factor = matches.is_present("FACTOR");
+    let nb = value_t!(matches.value_of("NB"), u64).unwrap_or(64);
+    let batch = value_t!(matches.value_of("BATCH"), u64).unwrap_or(0);
+    let small = value_t!(matches.value_of("SMALL"), u64).unwrap_or(16);
+    let input_a = matches.value_of("INPUT_A").map(String::from);
+    let input_b = matches.value_of("INPUT_B").map(String::from);
+    let output = matches.value_of("OUTPUT").map(String::from);
+    let seed = value_t!(matches.value_of("SEED"), u64).unwrap_or_else(|_| thread_rng().gen());
+    let freivalds = value_t!(matches.value_of("FREIVALDS"), u32).unwrap_or(0);
+    let dtype = match matches.value_of("DTYPE").unwrap_or("i32") {
+        "i64" => DType::I64,
+        "f32" => DType::F32,
+        "f64" => DType::F64,
+        "wrapping" => DType::Wrapping,
+        "checked" => DType::Checked,
+        _ => DType::I32,
+    };
 
     // Check if values are correct for the mandelbrot program
-    assert!(size > 0);
+    assert!(m > 0 && k > 0 && n > 0);
     assert!(num_threads > 0);
     assert!(num_of_runs > 0);
     assert!(code_config < 3);
+    assert!(blocks.mc > 0 && blocks.kc > 0 && blocks.nc > 0);
+    assert!((4..=64).contains(&small));
+    assert!(nb > 0);
 
     //
     println!(
-        "Configuration: \nsize={} num_threads: {}, num_of_runs: {}",
-        size, num_threads, num_of_runs
+        "Configuration: \nsize={}x{}x{} num_threads: {}, num_of_runs: {}, cutoff: {}, tiles: {}x{}x{}, tune: {}, dtype: {:?}, roofline: {}, seed: {}",
+        m, k, n, num_threads, num_of_runs, cutoff, blocks.mc, blocks.kc, blocks.nc, tune, dtype, roofline, seed
     );
 
     // Return the struct that can be used by the functions
     MatMulConfig {
-        size: size,
-        num_threads: num_threads,
-        num_of_runs: num_of_runs,
-        code_config: code_config,
+        m,
+        k,
+        n,
+        num_threads,
+        num_of_runs,
+        code_config,
+        cutoff,
+        blocks,
+        tune,
+        tune_file,
+        dtype,
+        roofline,
+        sparse,
+        gemv,
+        factor,
+        nb,
+        batch,
+        small,
+        input_a,
+        input_b,
+        output,
+        seed,
+        freivalds,
     }
 }
 
-pub fn matmul_serial(size: usize, m_a: &[i32], m_b: &[i32], m_c: &mut [i32]) {
-    let iter_c = m_c.chunks_mut(size);
-    let iter_a = m_a.chunks(size);
+// C (m x n) = A (m x k) * B (k x n), all row-major
+pub fn matmul_serial<T: Element>(
+    m: usize,
+    k: usize,
+    n: usize,
+    m_a: &[T],
+    m_b: &[T],
+    m_c: &mut [T],
+) {
+    assert_eq!(m_a.len(), m * k);
+    assert_eq!(m_b.len(), k * n);
+    assert_eq!(m_c.len(), m * n);
+
+    let iter_c = m_c.chunks_mut(n);
+    let iter_a = m_a.chunks(k);
 
     iter_c
         .zip(iter_a)
-        .enumerate()
-        .for_each(|(_, (c_slice, a_slice))| {
+        .for_each(|(c_slice, a_slice)| {
             c_slice.iter_mut().enumerate().for_each(|(col, c)| {
                 *c = a_slice
                     .iter()
                     .enumerate()
-                    .map(|(index, addr)| *addr * m_b[RM!(index, col as usize, size)])
+                    .map(|(index, addr)| *addr * m_b[RM!(index, col, n)])
                     .sum()
             });
         });
 }
 
-pub fn matmul_par_row(size: usize, m_a: &[i32], m_b: &[i32], m_c: &mut [i32]) {
-    let iter_c = m_c.par_chunks_mut(size);
-    let iter_a = m_a.par_chunks(size);
+pub fn matmul_par_row<T: Element>(
+    m: usize,
+    k: usize,
+    n: usize,
+    m_a: &[T],
+    m_b: &[T],
+    m_c: &mut [T],
+) {
+    assert_eq!(m_a.len(), m * k);
+    assert_eq!(m_b.len(), k * n);
+    assert_eq!(m_c.len(), m * n);
+
+    let iter_c = m_c.par_chunks_mut(n);
+    let iter_a = m_a.par_chunks(k);
 
     iter_c
         .zip(iter_a)
-        .enumerate()
-        .for_each(|(_, (c_slice, a_slice))| {
+        .for_each(|(c_slice, a_slice)| {
             c_slice.iter_mut().enumerate().for_each(|(col, c)| {
                 *c = a_slice
                     .iter()
                     .enumerate()
-                    .map(|(index, addr)| *addr * m_b[RM!(index, col as usize, size)])
+                    .map(|(index, addr)| *addr * m_b[RM!(index, col, n)])
                     .sum()
             });
         });
 }
 
-pub fn matmul_seq(m_a: &[i32], m_b: &[i32], dest: &mut [i32]) {
+pub fn matmul_seq<T: Element>(m: usize, k: usize, n: usize, m_a: &[T], m_b: &[T], dest: &mut [T]) {
     // Multiply in row-major order.
-    // D[i,j] = sum for all k A[i,k] * B[k,j]
-    let bits = dest.len().trailing_zeros() / 2;
-    let n = 1 << bits;
-    for i in 0..n {
+    // D[i,j] = sum for all p A[i,p] * B[p,j]
+    assert!(m_a.len() >= m * k && m_b.len() >= k * n);
+    for i in 0..m {
         for j in 0..n {
-            let mut sum = 0;
-            for k in 0..n {
+            let mut sum = T::default();
+            for p in 0..k {
                 sum +=
-                    unsafe { m_a.get_unchecked(i << bits | k) * m_b.get_unchecked(k << bits | j) };
+                    unsafe { *m_a.get_unchecked(RM!(i, p, k)) * *m_b.get_unchecked(RM!(p, j, n)) };
             }
-            dest[i << bits | j] = sum;
+            dest[RM!(i, j, n)] = sum;
         }
     }
 }
 
-// Multiply two square power of two matrices, given in Z-order.
-pub fn matmulz(a: &[i32], b: &[i32], dest: &mut [i32]) {
-    if a.len() <= MULT_CHUNK {
-        let bits = dest.len().trailing_zeros() / 2;
-        let size = 1 << bits;
-        matmul_serial(size, a, b, dest);
+// Multiply an m x k and a k x n matrix, given in the padded Z-order layout
+// where every side is a power of two.
+pub fn matmulz<T: Element>(m: usize, k: usize, n: usize, a: &[T], b: &[T], dest: &mut [T]) {
+    let split_m = m > zorder::zorder_tile(m);
+    let split_k = k > zorder::zorder_tile(k);
+    let split_n = n > zorder::zorder_tile(n);
+
+    if !(split_m || split_k || split_n) {
+        matmul_serial(m, k, n, a, b, dest);
         return;
     }
 
+    if !(split_m && split_k && split_n) {
+        matmulz_halves(m, k, n, a, b, dest);
+        return;
+    }
+
+    let (hm, hk, hn) = (m / 2, k / 2, n / 2);
+
     // Allocate uninitialized scratch space.
-    let mut tmp = vec![0; dest.len()];
+    let mut tmp = vec![T::default(); dest.len()];
 
     let (a1, a2, a3, a4) = quarter_chunks(a);
     let (b1, b2, b3, b4) = quarter_chunks(b);
@@ -266,14 +966,14 @@ pub fn matmulz(a: &[i32], b: &[i32], dest: &mut [i32]) {
         let (t1, t2, t3, t4) = quarter_chunks_mut(&mut tmp[..]);
         // Multiply 8 submatrices
         join8(
-            || matmulz(a1, b1, d1),
-            || matmulz(a1, b2, d2),
-            || matmulz(a3, b1, d3),
-            || matmulz(a3, b2, d4),
-            || matmulz(a2, b3, t1),
-            || matmulz(a2, b4, t2),
-            || matmulz(a4, b3, t3),
-            || matmulz(a4, b4, t4),
+            || matmulz(hm, hk, hn, a1, b1, d1),
+            || matmulz(hm, hk, hn, a1, b2, d2),
+            || matmulz(hm, hk, hn, a3, b1, d3),
+            || matmulz(hm, hk, hn, a3, b2, d4),
+            || matmulz(hm, hk, hn, a2, b3, t1),
+            || matmulz(hm, hk, hn, a2, b4, t2),
+            || matmulz(hm, hk, hn, a4, b3, t3),
+            || matmulz(hm, hk, hn, a4, b4, t4),
         );
     }
 
@@ -281,7 +981,80 @@ pub fn matmulz(a: &[i32], b: &[i32], dest: &mut [i32]) {
     rmatsum(tmp.as_mut(), dest);
 }
 
-fn quarter_chunks<'a>(v: &'a [i32]) -> (&'a [i32], &'a [i32], &'a [i32], &'a [i32]) {
+// One level of `matmulz` when some of the sides are down to a single tile:
+// the matrices are only cut along the longer sides, into halves or quarters,
+// and every block of the result is a task.
+fn matmulz_halves<T: Element>(m: usize, k: usize, n: usize, a: &[T], b: &[T], dest: &mut [T]) {
+    let split_m = m > zorder::zorder_tile(m);
+    let split_k = k > zorder::zorder_tile(k);
+    let split_n = n > zorder::zorder_tile(n);
+    let (hm, hk, hn) = (
+        if split_m { m / 2 } else { m },
+        if split_k { k / 2 } else { k },
+        if split_n { n / 2 } else { n },
+    );
+    let (parts_k, parts_n) = (1 + split_k as usize, 1 + split_n as usize);
+
+    let a_parts = split_parts(a, split_m, split_k);
+    let b_parts = split_parts(b, split_k, split_n);
+
+    // The second half of the shared dimension goes into scratch space
+    let mut tmp = vec![T::default(); if split_k { dest.len() } else { 0 }];
+    {
+        let dest_parts = split_parts_mut(dest, split_m, split_n);
+        let tmp_parts = split_parts_mut(&mut tmp[..], split_m, split_n);
+
+        rayon::scope(|s| {
+            for (p, parts) in vec![dest_parts, tmp_parts].into_iter().enumerate() {
+                for (block, d) in parts.into_iter().enumerate() {
+                    let (i, j) = (block / parts_n, block % parts_n);
+                    let (a, b) = (a_parts[i * parts_k + p], b_parts[p * parts_n + j]);
+                    s.spawn(move |_| matmulz(hm, hk, hn, a, b, d));
+                }
+            }
+        });
+    }
+
+    if split_k {
+        rmatsum(&tmp[..], dest);
+    }
+}
+
+// Blocks of a Z-order matrix cut along the given sides, in row-major order of
+// the blocks.
+fn split_parts<T>(v: &[T], split_rows: bool, split_cols: bool) -> Vec<&[T]> {
+    match (split_rows, split_cols) {
+        (true, true) => {
+            let (a, b, c, d) = quarter_chunks(v);
+            vec![a, b, c, d]
+        }
+        (false, false) => vec![v],
+        _ => {
+            let (a, b) = v.split_at(v.len() / 2);
+            vec![a, b]
+        }
+    }
+}
+
+fn split_parts_mut<T>(v: &mut [T], split_rows: bool, split_cols: bool) -> Vec<&mut [T]> {
+    if v.is_empty() {
+        return Vec::new();
+    }
+
+    match (split_rows, split_cols) {
+        (true, true) => {
+            let (a, b, c, d) = quarter_chunks_mut(v);
+            vec![a, b, c, d]
+        }
+        (false, false) => vec![v],
+        _ => {
+            let (a, b) = v.split_at_mut(v.len() / 2);
+            vec![a, b]
+        }
+    }
+}
+
+fn quarter_chunks<T>(v: &[T]) -> (&[T], &[T], &[T], &[T]) {
     let mid = v.len() / 2;
     let quarter = mid / 2;
     let (left, right) = v.split_at(mid);
@@ -290,9 +1063,7 @@ fn quarter_chunks<'a>(v: &'a [i32]) -> (&'a [i32], &'a [i32], &'a [i32], &'a [i3
     (a, b, c, d)
 }
 
-fn quarter_chunks_mut<'a>(
-    v: &'a mut [i32],
-) -> (&'a mut [i32], &'a mut [i32], &'a mut [i32], &'a mut [i32]) {
+fn quarter_chunks_mut<T>(v: &mut [T]) -> (&mut [T], &mut [T], &mut [T], &mut [T]) {
     let mid = v.len() / 2;
     let quarter = mid / 2;
     let (left, right) = v.split_at_mut(mid);
@@ -302,19 +1073,19 @@ fn quarter_chunks_mut<'a>(
 }
 
 // Any layout works, we're just adding by element.
-fn rmatsum(src: &[i32], dest: &mut [i32]) {
+fn rmatsum<T: Element>(src: &[T], dest: &mut [T]) {
     dest.par_iter_mut()
         .zip(src.par_iter())
         .for_each(|(d, s)| *d += *s);
 }
 
-fn rmatsub(src: &[i32], dest: &mut [i32]) {
+fn rmatsub<T: Element>(src: &[T], dest: &mut [T]) {
     dest.par_iter_mut()
         .zip(src.par_iter())
         .for_each(|(d, s)| *d -= *s);
 }
 
-fn rcopy(src: &[i32], dest: &mut [i32]) {
+fn rcopy<T: Element>(src: &[T], dest: &mut [T]) {
     if dest.len() <= LINEAR_CHUNK {
         dest.copy_from_slice(src);
         return;
@@ -341,6 +1112,7 @@ where
     (r1, r2, r3, r4)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn join8<F1, F2, F3, F4, F5, F6, F7, F8, R1, R2, R3, R4, R5, R6, R7, R8>(
     f1: F1,
     f2: F2,
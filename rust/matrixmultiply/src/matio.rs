@@ -0,0 +1,250 @@
+use crate::element::Element;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+
+// First bytes of the binary matrix files
+const BINARY_MAGIC: &[u8; 4] = b"MATB";
+const BINARY_HEADER: usize = 24;
+
+/*************************************
+ * Matrix files
+ *************************************/
+// Dense matrices are read from and written to two formats, picked by the file
+// extension:
+//  - Matrix Market (.mtx): `array` files are dense and column-major,
+//    `coordinate` files list the non-zero entries and are expanded. Both can
+//    be `general`, `symmetric` or `skew-symmetric`, and `pattern` entries are
+//    ones.
+//  - Raw binary (anything else): a 24 byte header, the magic "MATB", the kind
+//    of element ('i' or 'f'), its size in bytes, two zero bytes, then the rows
+//    and columns as u64, followed by the row-major elements, all little-endian.
+//    Files of another element type than the one benchmarked are converted.
+
+// Row-major dense matrix
+pub struct Matrix<T> {
+    pub rows: usize,
+    pub cols: usize,
+    pub data: Vec<T>,
+}
+
+// Entries of a Matrix Market file as (row, column, value), zero-based
+pub struct Entries<T> {
+    pub rows: usize,
+    pub cols: usize,
+    pub entries: Vec<(usize, usize, T)>,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub fn is_mtx(path: &str) -> bool {
+    path.to_lowercase().ends_with(".mtx")
+}
+
+pub fn load<T: Element>(path: &str) -> io::Result<Matrix<T>> {
+    if is_mtx(path) {
+        let mtx = read_mtx_entries(path)?;
+        let mut data = vec![T::default(); mtx.rows * mtx.cols];
+        for (row, col, value) in mtx.entries {
+            data[RM!(row, col, mtx.cols)] = value;
+        }
+        Ok(Matrix {
+            rows: mtx.rows,
+            cols: mtx.cols,
+            data,
+        })
+    } else {
+        read_binary(path)
+    }
+}
+
+pub fn save<T: Element>(path: &str, rows: usize, cols: usize, data: &[T]) -> io::Result<()> {
+    assert_eq!(data.len(), rows * cols);
+    if is_mtx(path) {
+        write_mtx(path, rows, cols, data)
+    } else {
+        write_binary(path, rows, cols, data)
+    }
+}
+
+// Reads the entries of a Matrix Market file, mirroring the symmetric ones
+pub fn read_mtx_entries<T: Element>(path: &str) -> io::Result<Entries<T>> {
+    let contents = fs::read_to_string(path)?;
+    let mut lines = contents.lines();
+
+    let header = lines
+        .next()
+        .ok_or_else(|| invalid(format!("{}: empty file", path)))?
+        .to_lowercase();
+    let banner: Vec<&str> = header.split_whitespace().collect();
+    if banner.len() != 5 || banner[0] != "%%matrixmarket" || banner[1] != "matrix" {
+        return Err(invalid(format!("{}: not a Matrix Market matrix", path)));
+    }
+    let coordinate = match banner[2] {
+        "coordinate" => true,
+        "array" => false,
+        format => return Err(invalid(format!("{}: unknown format {}", path, format))),
+    };
+    let pattern = match banner[3] {
+        "real" | "integer" | "double" => false,
+        "pattern" if coordinate => true,
+        field => return Err(invalid(format!("{}: unsupported field {}", path, field))),
+    };
+    let (symmetric, skew) = match banner[4] {
+        "general" => (false, false),
+        "symmetric" => (true, false),
+        "skew-symmetric" => (true, true),
+        symmetry => {
+            return Err(invalid(format!(
+                "{}: unsupported symmetry {}",
+                path, symmetry
+            )))
+        }
+    };
+
+    // Comments may follow the banner
+    let mut tokens = lines
+        .filter(|line| !line.trim_start().starts_with('%'))
+        .flat_map(|line| line.split_whitespace());
+    let mut next = |what: &'static str| -> io::Result<(&'static str, &str)> {
+        let text = tokens
+            .next()
+            .ok_or_else(|| invalid(format!("{}: missing {}", path, what)))?;
+        Ok((what, text))
+    };
+    let number = |(what, text): (&str, &str)| -> io::Result<usize> {
+        text.parse::<f64>()
+            .map(|number| number as usize)
+            .map_err(|err| invalid(format!("{}: bad {}: {}", path, what, err)))
+    };
+    let value = |(what, text): (&str, &str)| -> io::Result<T> {
+        T::from_text(text).ok_or_else(|| invalid(format!("{}: bad {}: {}", path, what, text)))
+    };
+
+    let rows = number(next("rows")?)?;
+    let cols = number(next("columns")?)?;
+    let mut entries = Vec::new();
+
+    let mut push = |row: usize, col: usize, value: T| {
+        entries.push((row, col, value));
+        if symmetric && row != col {
+            let mirrored = if skew { T::default() - value } else { value };
+            entries.push((col, row, mirrored));
+        }
+    };
+
+    if coordinate {
+        let nnz = number(next("entry count")?)?;
+        for _ in 0..nnz {
+            let row = number(next("row index")?)?;
+            let col = number(next("column index")?)?;
+            let value = if pattern {
+                T::from_i32(1)
+            } else {
+                value(next("value")?)?
+            };
+            if row == 0 || row > rows || col == 0 || col > cols {
+                return Err(invalid(format!(
+                    "{}: entry ({}, {}) out of bounds",
+                    path, row, col
+                )));
+            }
+            push(row - 1, col - 1, value);
+        }
+    } else {
+        // Column-major, only the lower triangle of symmetric matrices, and
+        // without the diagonal for skew-symmetric ones
+        for col in 0..cols {
+            let first_row = match (symmetric, skew) {
+                (false, _) => 0,
+                (true, false) => col,
+                (true, true) => col + 1,
+            };
+            for row in first_row..rows {
+                push(row, col, value(next("value")?)?);
+            }
+        }
+    }
+
+    Ok(Entries {
+        rows,
+        cols,
+        entries,
+    })
+}
+
+// Dense `array` file, column-major
+fn write_mtx<T: Element>(path: &str, rows: usize, cols: usize, data: &[T]) -> io::Result<()> {
+    let mut out = BufWriter::new(fs::File::create(path)?);
+    let field = if T::IS_INTEGER { "integer" } else { "real" };
+
+    writeln!(out, "%%MatrixMarket matrix array {} general", field)?;
+    writeln!(out, "{} {}", rows, cols)?;
+    for col in 0..cols {
+        for row in 0..rows {
+            writeln!(out, "{}", data[RM!(row, col, cols)].to_text())?;
+        }
+    }
+    out.flush()
+}
+
+fn write_binary<T: Element>(path: &str, rows: usize, cols: usize, data: &[T]) -> io::Result<()> {
+    let mut bytes = Vec::with_capacity(BINARY_HEADER + data.len() * T::BYTES);
+    let kind = if T::IS_INTEGER { b'i' } else { b'f' };
+
+    bytes.extend_from_slice(BINARY_MAGIC);
+    bytes.extend_from_slice(&[kind, T::BYTES as u8, 0, 0]);
+    bytes.extend_from_slice(&(rows as u64).to_le_bytes());
+    bytes.extend_from_slice(&(cols as u64).to_le_bytes());
+    data.iter().for_each(|x| x.to_le(&mut bytes));
+
+    fs::write(path, bytes)
+}
+
+fn read_binary<T: Element>(path: &str) -> io::Result<Matrix<T>> {
+    let bytes = fs::read(path)?;
+    if bytes.len() < BINARY_HEADER || &bytes[..4] != BINARY_MAGIC {
+        return Err(invalid(format!("{}: not a binary matrix file", path)));
+    }
+
+    let (kind, size) = (bytes[4], bytes[5] as usize);
+    let mut dim = [0u8; 8];
+    dim.copy_from_slice(&bytes[8..16]);
+    let rows = u64::from_le_bytes(dim) as usize;
+    dim.copy_from_slice(&bytes[16..24]);
+    let cols = u64::from_le_bytes(dim) as usize;
+
+    let body = &bytes[BINARY_HEADER..];
+    if !matches!((kind, size), (b'i', 4) | (b'i', 8) | (b'f', 4) | (b'f', 8)) {
+        return Err(invalid(format!("{}: unknown element type", path)));
+    }
+    let expected = rows
+        .checked_mul(cols)
+        .and_then(|elements| elements.checked_mul(size));
+    if expected != Some(body.len()) {
+        return Err(invalid(format!(
+            "{}: expected {}x{} elements of {} bytes",
+            path, rows, cols, size
+        )));
+    }
+
+    let own_kind = if T::IS_INTEGER { b'i' } else { b'f' };
+    let data = body
+        .chunks(size)
+        .map(|x| {
+            if (kind, size) == (own_kind, T::BYTES) {
+                T::from_le(x)
+            } else {
+                match (kind, size) {
+                    (b'i', 4) => T::from_f64(<i32 as Element>::from_le(x) as f64),
+                    (b'i', _) => T::from_f64(<i64 as Element>::from_le(x) as f64),
+                    (b'f', 4) => T::from_f64(<f32 as Element>::from_le(x) as f64),
+                    _ => T::from_f64(<f64 as Element>::from_le(x)),
+                }
+            }
+        })
+        .collect();
+
+    Ok(Matrix { rows, cols, data })
+}
@@ -0,0 +1,310 @@
+use rayon::prelude::*;
+use std::ops::{Add, Mul};
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+// Cache blocking of the packed kernel, in elements
+const PACKED_MC: usize = 96;
+const PACKED_KC: usize = 256;
+const PACKED_NC: usize = 2048;
+
+/*************************************
+ * BLIS-style packed GEMM
+ *************************************/
+// The classic five loop structure: B is packed into NR wide column panels for
+// every NC x KC slab, A into MR tall row panels for every MC x KC block, and a
+// register blocked micro-kernel computes MR x NR pieces of C from two panels.
+// The MC bands of C are independent and are the Rayon tasks, each packing its
+// own panel of A. The micro-kernel uses AVX2/FMA when the CPU has it and a
+// scalar loop otherwise.
+
+// Computes an MR x NR block of A * B from `kc` packed columns of A and rows of
+// B and stores it row-major in the accumulator buffer.
+pub type MicroKernel<T> = fn(usize, &[T], &[T], &mut [T]);
+
+pub trait PackedElement:
+    Copy + Default + Send + Sync + Add<Output = Self> + Mul<Output = Self>
+{
+    const MR: usize;
+    const NR: usize;
+
+    fn micro_kernel() -> MicroKernel<Self>;
+}
+
+impl PackedElement for f32 {
+    const MR: usize = 8;
+    const NR: usize = 8;
+
+    fn micro_kernel() -> MicroKernel<f32> {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+                return kernel_f32_avx2;
+            }
+        }
+        kernel_scalar::<f32>
+    }
+}
+
+impl PackedElement for f64 {
+    const MR: usize = 4;
+    const NR: usize = 8;
+
+    fn micro_kernel() -> MicroKernel<f64> {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+                return kernel_f64_avx2;
+            }
+        }
+        kernel_scalar::<f64>
+    }
+}
+
+fn kernel_scalar<T: PackedElement>(kc: usize, a: &[T], b: &[T], acc: &mut [T]) {
+    acc.iter_mut().for_each(|c| *c = T::default());
+
+    for p in 0..kc {
+        let a_col = &a[(p * T::MR)..((p + 1) * T::MR)];
+        let b_row = &b[(p * T::NR)..((p + 1) * T::NR)];
+
+        acc.chunks_mut(T::NR)
+            .zip(a_col.iter())
+            .for_each(|(c_row, &a)| {
+                c_row
+                    .iter_mut()
+                    .zip(b_row.iter())
+                    .for_each(|(c, &b)| *c = *c + a * b)
+            });
+    }
+}
+
+// 8 x 8 f32 block, one ymm accumulator per row of C
+#[cfg(target_arch = "x86_64")]
+fn kernel_f32_avx2(kc: usize, a: &[f32], b: &[f32], acc: &mut [f32]) {
+    assert!(a.len() >= kc * 8 && b.len() >= kc * 8 && acc.len() >= 64);
+    // Only selected after checking for AVX2 and FMA
+    unsafe { kernel_f32_avx2_inner(kc, a.as_ptr(), b.as_ptr(), acc.as_mut_ptr()) }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn kernel_f32_avx2_inner(kc: usize, a: *const f32, b: *const f32, acc: *mut f32) {
+    let mut c = [_mm256_setzero_ps(); 8];
+
+    for p in 0..kc {
+        let b_row = _mm256_loadu_ps(b.add(p * 8));
+        for (i, c_row) in c.iter_mut().enumerate() {
+            let a_i = _mm256_broadcast_ss(&*a.add(p * 8 + i));
+            *c_row = _mm256_fmadd_ps(a_i, b_row, *c_row);
+        }
+    }
+
+    for (i, c_row) in c.iter().enumerate() {
+        _mm256_storeu_ps(acc.add(i * 8), *c_row);
+    }
+}
+
+// 4 x 8 f64 block, two ymm accumulators per row of C
+#[cfg(target_arch = "x86_64")]
+fn kernel_f64_avx2(kc: usize, a: &[f64], b: &[f64], acc: &mut [f64]) {
+    assert!(a.len() >= kc * 4 && b.len() >= kc * 8 && acc.len() >= 32);
+    // Only selected after checking for AVX2 and FMA
+    unsafe { kernel_f64_avx2_inner(kc, a.as_ptr(), b.as_ptr(), acc.as_mut_ptr()) }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2,fma")]
+unsafe fn kernel_f64_avx2_inner(kc: usize, a: *const f64, b: *const f64, acc: *mut f64) {
+    let mut c = [[_mm256_setzero_pd(); 2]; 4];
+
+    for p in 0..kc {
+        let b_lo = _mm256_loadu_pd(b.add(p * 8));
+        let b_hi = _mm256_loadu_pd(b.add(p * 8 + 4));
+        for (i, c_row) in c.iter_mut().enumerate() {
+            let a_i = _mm256_broadcast_sd(&*a.add(p * 4 + i));
+            c_row[0] = _mm256_fmadd_pd(a_i, b_lo, c_row[0]);
+            c_row[1] = _mm256_fmadd_pd(a_i, b_hi, c_row[1]);
+        }
+    }
+
+    for (i, c_row) in c.iter().enumerate() {
+        _mm256_storeu_pd(acc.add(i * 8), c_row[0]);
+        _mm256_storeu_pd(acc.add(i * 8 + 4), c_row[1]);
+    }
+}
+
+// Packs the `kc` x `nc` slab of B starting at (pc, jc) into NR wide panels,
+// each panel stored row after row and padded with zeros.
+#[allow(clippy::too_many_arguments)]
+fn pack_b<T: PackedElement>(
+    parallel: bool,
+    n: usize,
+    m_b: &[T],
+    pc: usize,
+    kc: usize,
+    jc: usize,
+    nc: usize,
+    packed: &mut [T],
+) {
+    let pack_panel = |(panel, dest): (usize, &mut [T])| {
+        let col_start = jc + panel * T::NR;
+        let width = std::cmp::min(T::NR, jc + nc - col_start);
+
+        dest.chunks_mut(T::NR)
+            .enumerate()
+            .for_each(|(p, dest_row)| {
+                let start = RM!(pc + p, col_start, n);
+                dest_row[..width].copy_from_slice(&m_b[start..(start + width)]);
+                dest_row[width..].iter_mut().for_each(|d| *d = T::default());
+            });
+    };
+
+    if parallel {
+        packed
+            .par_chunks_mut(kc * T::NR)
+            .enumerate()
+            .for_each(pack_panel);
+    } else {
+        packed
+            .chunks_mut(kc * T::NR)
+            .enumerate()
+            .for_each(pack_panel);
+    }
+}
+
+// Packs the `mc` x `kc` block of A starting at (ic, pc) into MR tall panels,
+// each panel stored column after column and padded with zeros.
+fn pack_a<T: PackedElement>(
+    k: usize,
+    m_a: &[T],
+    ic: usize,
+    mc: usize,
+    pc: usize,
+    kc: usize,
+    packed: &mut [T],
+) {
+    packed
+        .chunks_mut(kc * T::MR)
+        .enumerate()
+        .for_each(|(panel, dest)| {
+            let row_start = ic + panel * T::MR;
+            let height = std::cmp::min(T::MR, ic + mc - row_start);
+
+            dest.chunks_mut(T::MR)
+                .enumerate()
+                .for_each(|(p, dest_col)| {
+                    for (i, d) in dest_col.iter_mut().enumerate() {
+                        *d = if i < height {
+                            m_a[RM!(row_start + i, pc + p, k)]
+                        } else {
+                            T::default()
+                        };
+                    }
+                });
+        });
+}
+
+// Adds the A block times the packed B slab into a band of C rows
+#[allow(clippy::too_many_arguments)]
+fn macro_kernel<T: PackedElement>(
+    kernel: MicroKernel<T>,
+    n: usize,
+    packed_a: &[T],
+    packed_b: &[T],
+    mc: usize,
+    kc: usize,
+    jc: usize,
+    nc: usize,
+    c_band: &mut [T],
+) {
+    let mut acc = vec![T::default(); T::MR * T::NR];
+
+    for (jr, b_panel) in packed_b.chunks(kc * T::NR).enumerate() {
+        let col_start = jc + jr * T::NR;
+        let width = std::cmp::min(T::NR, jc + nc - col_start);
+
+        for (ir, a_panel) in packed_a.chunks(kc * T::MR).enumerate() {
+            let row_start = ir * T::MR;
+            let height = std::cmp::min(T::MR, mc - row_start);
+
+            kernel(kc, a_panel, b_panel, &mut acc[..]);
+
+            for i in 0..height {
+                let c_start = RM!(row_start + i, col_start, n);
+                c_band[c_start..(c_start + width)]
+                    .iter_mut()
+                    .zip(acc[(i * T::NR)..(i * T::NR + width)].iter())
+                    .for_each(|(c, &r)| *c = *c + r);
+            }
+        }
+    }
+}
+
+fn gemm_packed<T: PackedElement>(
+    parallel: bool,
+    m: usize,
+    k: usize,
+    n: usize,
+    m_a: &[T],
+    m_b: &[T],
+    m_c: &mut [T],
+) {
+    assert_eq!(m_a.len(), m * k);
+    assert_eq!(m_b.len(), k * n);
+    assert_eq!(m_c.len(), m * n);
+
+    let kernel = T::micro_kernel();
+    m_c.iter_mut().for_each(|c| *c = T::default());
+
+    let mut packed_b = vec![T::default(); PACKED_KC * PACKED_NC.div_ceil(T::NR) * T::NR];
+
+    for jc in (0..n).step_by(PACKED_NC) {
+        let nc = std::cmp::min(PACKED_NC, n - jc);
+
+        for pc in (0..k).step_by(PACKED_KC) {
+            let kc = std::cmp::min(PACKED_KC, k - pc);
+            let packed_b = &mut packed_b[..(kc * nc.div_ceil(T::NR) * T::NR)];
+            pack_b(parallel, n, m_b, pc, kc, jc, nc, packed_b);
+            let packed_b = &*packed_b;
+
+            let band = |(band, c_band): (usize, &mut [T])| {
+                let ic = band * PACKED_MC;
+                let mc = c_band.len() / n;
+                let mut packed_a = vec![T::default(); kc * mc.div_ceil(T::MR) * T::MR];
+                pack_a(k, m_a, ic, mc, pc, kc, &mut packed_a[..]);
+                macro_kernel(kernel, n, &packed_a[..], packed_b, mc, kc, jc, nc, c_band);
+            };
+
+            if parallel {
+                m_c.par_chunks_mut(PACKED_MC * n).enumerate().for_each(band);
+            } else {
+                m_c.chunks_mut(PACKED_MC * n).enumerate().for_each(band);
+            }
+        }
+    }
+}
+
+// C (m x n) = A (m x k) * B (k x n), all row-major
+pub fn gemm_packed_serial<T: PackedElement>(
+    m: usize,
+    k: usize,
+    n: usize,
+    m_a: &[T],
+    m_b: &[T],
+    m_c: &mut [T],
+) {
+    gemm_packed(false, m, k, n, m_a, m_b, m_c);
+}
+
+pub fn gemm_packed_par<T: PackedElement>(
+    m: usize,
+    k: usize,
+    n: usize,
+    m_a: &[T],
+    m_b: &[T],
+    m_c: &mut [T],
+) {
+    gemm_packed(true, m, k, n, m_a, m_b, m_c);
+}
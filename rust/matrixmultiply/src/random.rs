@@ -0,0 +1,86 @@
+use crate::element::{self, Element};
+use crate::gemv::gemv_par_chunked;
+use crate::MatMulConfig;
+use rand::distributions::Uniform;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::Instant;
+
+// Inputs are uniform in -RANDOM_RANGE..=RANDOM_RANGE, small enough that i32
+// products of a few thousand columns do not overflow
+const RANDOM_RANGE: i32 = 10;
+
+/*************************************
+ * Random inputs and Freivalds' check
+ *************************************/
+// Every input comes from the generator seeded with --seed, so a run can be
+// repeated exactly. Freivalds' check verifies C = A B without the serial
+// kernel: for a random 0/1 vector r, A (B r) and C r are three matrix-vector
+// products, O(n^2) instead of O(n^3), and a wrong C passes a trial with
+// probability at most 1/2.
+
+pub fn random_matrix<T: Element>(rng: &mut StdRng, len: usize) -> Vec<T> {
+    rng.sample_iter(&Uniform::new_inclusive(-RANDOM_RANGE, RANDOM_RANGE))
+        .take(len)
+        .map(T::from_i32)
+        .collect()
+}
+
+// Whether C (m x n) = A (m x k) * B (k x n) passed `trials` trials
+pub fn freivalds<T: Element>(
+    seed: u64,
+    trials: u32,
+    dims: (usize, usize, usize),
+    m_a: &[T],
+    m_b: &[T],
+    m_c: &[T],
+) -> bool {
+    let (m, k, n) = dims;
+
+    (0..trials).all(|trial| {
+        let mut rng = StdRng::seed_from_u64(seed.wrapping_add(trial as u64 + 1));
+        let r: Vec<T> = (0..n).map(|_| T::from_i32(rng.gen_range(0, 2))).collect();
+
+        let mut br = vec![T::default(); k];
+        let mut abr = vec![T::default(); m];
+        let mut cr = vec![T::default(); m];
+        gemv_par_chunked(n, m_b, &r[..], &mut br[..]);
+        gemv_par_chunked(k, m_a, &br[..], &mut abr[..]);
+        gemv_par_chunked(n, m_c, &r[..], &mut cr[..]);
+
+        element::max_rel_error(&abr[..], &cr[..]) <= T::TOLERANCE
+    })
+}
+
+// Runs --freivalds trials on a result, if any were asked for
+pub fn check_freivalds<T: Element>(
+    matmul_config: &MatMulConfig,
+    name: &str,
+    m_a: &[T],
+    m_b: &[T],
+    m_c: &[T],
+) {
+    let trials = matmul_config.freivalds;
+    if trials == 0 {
+        return;
+    }
+
+    let check_start = Instant::now();
+    let passed = freivalds(
+        matmul_config.seed,
+        trials,
+        matmul_config.dims(),
+        m_a,
+        m_b,
+        m_c,
+    );
+    let check_end = Instant::now();
+
+    assert!(passed, "{} failed Freivalds' check", name);
+    println!(
+        "++++ \t\t({}: {} Freivalds trials passed in {:?} ms)",
+        name,
+        trials,
+        check_end.duration_since(check_start).as_micros() as f64 / 1000_f64
+    );
+}
@@ -0,0 +1,155 @@
+use crate::element::Element;
+use crate::packed::PackedElement;
+use rayon::prelude::*;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+// Elements of each of the three STREAM arrays, far larger than the caches
+const STREAM_LEN: usize = 1 << 22;
+// Shared dimension of the micro-kernel calls timed for the compute roof
+const PEAK_KC: usize = 256;
+// Micro-kernel calls per thread for the compute roof
+const PEAK_CALLS: usize = 20_000;
+
+/*************************************
+ * Throughput and roofline
+ *************************************/
+// A multiply does 2*M*N*K operations and has to move at least A, B and C
+// between memory and the cores once, which gives the achieved rate, the
+// effective bandwidth and the arithmetic intensity of every variant. With
+// --roofline the machine's roofs are measured first: the memory roof with a
+// STREAM triad and a compute roof for f32 and for f64 with the packed
+// micro-kernel of that type on panels that stay in L1, so each floating point
+// variant can be placed under min(peak GFLOPS, intensity * peak bandwidth).
+// There is no integer micro-kernel to measure a compute roof with, so the
+// integer variants get their rates but no place under the roofline.
+
+// Measured peaks of the machine
+#[derive(Copy, Clone, Debug)]
+pub struct Roofline {
+    pub peak_bandwidth: f64,
+    pub peak_gflops_f32: f64,
+    pub peak_gflops_f64: f64,
+}
+
+impl Roofline {
+    // Compute roof of the element type, none for the integer types
+    pub fn peak_gflops<T: Element>(&self) -> Option<f64> {
+        match (T::IS_INTEGER, T::BYTES) {
+            (true, _) => None,
+            (false, 4) => Some(self.peak_gflops_f32),
+            (false, _) => Some(self.peak_gflops_f64),
+        }
+    }
+}
+
+// Parallel a = b + s * c, in GB/s counting the two reads and the write
+fn stream_triad(num_runs: u32) -> f64 {
+    let mut a = vec![0.0_f64; STREAM_LEN];
+    let b = vec![1.0_f64; STREAM_LEN];
+    let c = vec![2.0_f64; STREAM_LEN];
+    let scalar = black_box(3.0);
+
+    let mut triad_time = Duration::new(100, 0);
+
+    for _ in 0..num_runs {
+        let triad_start = Instant::now();
+        a.par_iter_mut()
+            .zip(b.par_iter().zip(c.par_iter()))
+            .for_each(|(a, (b, c))| *a = *b + scalar * *c);
+        let triad_end = Instant::now();
+
+        black_box(&a);
+        triad_time = std::cmp::min(triad_time, triad_end.duration_since(triad_start));
+    }
+
+    (3 * STREAM_LEN * std::mem::size_of::<f64>()) as f64 / triad_time.as_secs_f64() / 1e9
+}
+
+// Every thread calls the micro-kernel of P on the same cache resident panels
+fn micro_kernel_peak<P: PackedElement + Element>(num_runs: u32) -> f64 {
+    let kernel = P::micro_kernel();
+    let threads = rayon::current_num_threads();
+    let a = vec![P::from_i32(1); PEAK_KC * P::MR];
+    let b = vec![P::from_i32(1); PEAK_KC * P::NR];
+
+    let mut peak_time = Duration::new(100, 0);
+
+    for _ in 0..num_runs {
+        let peak_start = Instant::now();
+        (0..threads).into_par_iter().for_each(|_| {
+            let mut acc = vec![P::default(); P::MR * P::NR];
+            for _ in 0..PEAK_CALLS {
+                kernel(PEAK_KC, black_box(&a[..]), black_box(&b[..]), &mut acc[..]);
+            }
+            black_box(&acc);
+        });
+        let peak_end = Instant::now();
+
+        peak_time = std::cmp::min(peak_time, peak_end.duration_since(peak_start));
+    }
+
+    let flops = 2 * P::MR * P::NR * PEAK_KC * PEAK_CALLS * threads;
+    flops as f64 / peak_time.as_secs_f64() / 1e9
+}
+
+pub fn measure(num_runs: u32) -> Roofline {
+    let roofline = Roofline {
+        peak_bandwidth: stream_triad(num_runs),
+        peak_gflops_f32: micro_kernel_peak::<f32>(num_runs),
+        peak_gflops_f64: micro_kernel_peak::<f64>(num_runs),
+    };
+
+    println!(
+        "[matmul-rust roofline]: \tpeak {:.2} GB/s (STREAM triad), {:.2} / {:.2} GFLOPS (f32 / f64 micro-kernel)",
+        roofline.peak_bandwidth, roofline.peak_gflops_f32, roofline.peak_gflops_f64
+    );
+    println!(
+        "++++ \t\t(ridge point at {:.2} / {:.2} flops/byte)\n",
+        roofline.peak_gflops_f32 / roofline.peak_bandwidth,
+        roofline.peak_gflops_f64 / roofline.peak_bandwidth
+    );
+
+    roofline
+}
+
+// Prints the rate, effective bandwidth and intensity of an m x k times k x n
+// multiply of T elements that took `time`, and its place under the roofline.
+pub fn print_rates<T: Element>(
+    dims: (usize, usize, usize),
+    time: Duration,
+    roofline: Option<&Roofline>,
+) {
+    let (m, k, n) = dims;
+    let ops = 2.0 * m as f64 * k as f64 * n as f64;
+    let bytes = ((m * k + k * n + m * n) * T::BYTES) as f64;
+    let seconds = time.as_secs_f64();
+
+    // Integer results are operations rather than floating point ones
+    let unit = if T::IS_INTEGER { "GOPS" } else { "GFLOPS" };
+    let rate = ops / seconds / 1e9;
+    let intensity = ops / bytes;
+
+    println!(
+        "++++ \t\t({:.2} {}, {:.2} GB/s, {:.2} ops/byte)",
+        rate,
+        unit,
+        bytes / seconds / 1e9,
+        intensity
+    );
+
+    let peak = roofline.and_then(|roofline| roofline.peak_gflops::<T>());
+    if let (Some(roofline), Some(peak)) = (roofline, peak) {
+        let bound = f64::min(peak, intensity * roofline.peak_bandwidth);
+        println!(
+            "++++ \t\t({:.1}% of the {:.2} GFLOPS roofline bound, {})",
+            100.0 * rate / bound,
+            bound,
+            if bound < peak {
+                "memory bound"
+            } else {
+                "compute bound"
+            }
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use crate::element::{self, Element};
+use crate::matio::Entries;
+use crate::{print_speedup, time_kernel, MatMulConfig};
+use rayon::prelude::*;
+use std::time::Duration;
+
+/*************************************
+ * Sparse matrices
+ *************************************/
+// A is stored in CSR, one row after the other, and every row of y = A x or
+// C = A B only depends on one row of A, so the rows are split between the
+// tasks. Splitting them into parts of as many rows is what `par_rows` does;
+// on matrices whose rows have very different lengths some parts get far more
+// non-zeros than others, and `par_nnz` instead cuts the rows where the parts
+// have the same number of non-zeros. CSC is there for the column view and its
+// scatter SpMV, which checks the CSR kernels.
+
+// Compressed sparse rows
+pub struct Csr<T> {
+    pub rows: usize,
+    pub cols: usize,
+    pub row_ptr: Vec<usize>,
+    pub col_idx: Vec<usize>,
+    pub values: Vec<T>,
+}
+
+// Compressed sparse columns
+pub struct Csc<T> {
+    pub rows: usize,
+    pub cols: usize,
+    pub col_ptr: Vec<usize>,
+    pub row_idx: Vec<usize>,
+    pub values: Vec<T>,
+}
+
+impl<T: Element> Csr<T> {
+    // Sorts the entries by row and column, summing duplicates
+    pub fn from_entries(mtx: Entries<T>) -> Csr<T> {
+        let mut entries = mtx.entries;
+        entries.par_sort_unstable_by_key(|&(row, col, _)| (row, col));
+
+        let mut row_ptr = vec![0; mtx.rows + 1];
+        let mut col_idx: Vec<usize> = Vec::with_capacity(entries.len());
+        let mut values: Vec<T> = Vec::with_capacity(entries.len());
+        let mut last = None;
+
+        for (row, col, value) in entries {
+            if last == Some((row, col)) {
+                *values.last_mut().unwrap() += value;
+            } else {
+                row_ptr[row + 1] += 1;
+                col_idx.push(col);
+                values.push(value);
+                last = Some((row, col));
+            }
+        }
+        for row in 0..mtx.rows {
+            row_ptr[row + 1] += row_ptr[row];
+        }
+
+        Csr {
+            rows: mtx.rows,
+            cols: mtx.cols,
+            row_ptr,
+            col_idx,
+            values,
+        }
+    }
+
+    // Keeps the non-zero elements of a row-major matrix
+    pub fn from_dense(rows: usize, cols: usize, data: &[T]) -> Csr<T> {
+        let entries = data
+            .chunks(cols)
+            .enumerate()
+            .flat_map(|(row, values)| {
+                values
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, value)| **value != T::default())
+                    .map(move |(col, value)| (row, col, *value))
+            })
+            .collect();
+
+        Csr::from_entries(Entries {
+            rows,
+            cols,
+            entries,
+        })
+    }
+
+    // Ones with power-law row lengths: row i has about cols / (i + 1)
+    // consecutive non-zeros, so the first rows hold most of the matrix.
+    pub fn power_law(rows: usize, cols: usize) -> Csr<T> {
+        let entries = (0..rows)
+            .flat_map(|row| {
+                let len = std::cmp::max(1, cols / (row + 1));
+                let start = (row * 7919) % cols;
+                (0..len).map(move |j| (row, (start + j) % cols, T::from_i32(1)))
+            })
+            .collect();
+
+        Csr::from_entries(Entries {
+            rows,
+            cols,
+            entries,
+        })
+    }
+
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn to_csc(&self) -> Csc<T> {
+        let mut col_ptr = vec![0; self.cols + 1];
+        self.col_idx.iter().for_each(|&col| col_ptr[col + 1] += 1);
+        for col in 0..self.cols {
+            col_ptr[col + 1] += col_ptr[col];
+        }
+
+        let mut next = col_ptr.clone();
+        let mut row_idx = vec![0; self.nnz()];
+        let mut values = vec![T::default(); self.nnz()];
+        for row in 0..self.rows {
+            for index in self.row_ptr[row]..self.row_ptr[row + 1] {
+                let col = self.col_idx[index];
+                row_idx[next[col]] = row;
+                values[next[col]] = self.values[index];
+                next[col] += 1;
+            }
+        }
+
+        Csc {
+            rows: self.rows,
+            cols: self.cols,
+            col_ptr,
+            row_idx,
+            values,
+        }
+    }
+
+    fn row_dot(&self, row: usize, x: &[T]) -> T {
+        let range = self.row_ptr[row]..self.row_ptr[row + 1];
+        self.col_idx[range.clone()]
+            .iter()
+            .zip(self.values[range].iter())
+            .map(|(&col, &value)| value * x[col])
+            .sum()
+    }
+
+    // Adds row `row` of A times the k x n row-major B into a row of C
+    fn row_times(&self, row: usize, b: &[T], n: usize, c_row: &mut [T]) {
+        c_row.iter_mut().for_each(|c| *c = T::default());
+        for index in self.row_ptr[row]..self.row_ptr[row + 1] {
+            let value = self.values[index];
+            let b_row = &b[RM!(self.col_idx[index], 0, n)..RM!(self.col_idx[index] + 1, 0, n)];
+            c_row
+                .iter_mut()
+                .zip(b_row.iter())
+                .for_each(|(c, b)| *c += value * *b);
+        }
+    }
+}
+
+// y = A x, one row after the other
+pub fn spmv_serial<T: Element>(a: &Csr<T>, x: &[T], y: &mut [T]) {
+    y.iter_mut()
+        .enumerate()
+        .for_each(|(row, y)| *y = a.row_dot(row, x));
+}
+
+// y = A x, scattering every column of A
+pub fn spmv_csc_serial<T: Element>(a: &Csc<T>, x: &[T], y: &mut [T]) {
+    assert_eq!(x.len(), a.cols);
+    assert_eq!(y.len(), a.rows);
+
+    y.iter_mut().for_each(|y| *y = T::default());
+    for (col, x) in x.iter().enumerate() {
+        for index in a.col_ptr[col]..a.col_ptr[col + 1] {
+            y[a.row_idx[index]] += a.values[index] * *x;
+        }
+    }
+}
+
+// C = A B with B dense k x n, one row after the other
+pub fn spmm_serial<T: Element>(a: &Csr<T>, b: &[T], n: usize, c: &mut [T]) {
+    c.chunks_mut(n)
+        .enumerate()
+        .for_each(|(row, c_row)| a.row_times(row, b, n, c_row));
+}
+
+// Boundaries of `parts` runs of the same number of rows
+pub fn row_partition<T>(a: &Csr<T>, parts: usize) -> Vec<usize> {
+    (0..=parts).map(|part| a.rows * part / parts).collect()
+}
+
+// Boundaries of `parts` runs of rows with about the same number of non-zeros,
+// each one starting at the first row past its share of the non-zeros.
+pub fn nnz_partition<T: Element>(a: &Csr<T>, parts: usize) -> Vec<usize> {
+    (0..=parts)
+        .map(|part| {
+            let target = a.nnz() * part / parts;
+            if part == parts {
+                a.rows
+            } else {
+                a.row_ptr[..a.rows].partition_point(|&start| start < target)
+            }
+        })
+        .collect()
+}
+
+// Largest number of non-zeros in a part over the average
+pub fn imbalance<T: Element>(a: &Csr<T>, bounds: &[usize]) -> f64 {
+    let largest = bounds
+        .windows(2)
+        .map(|part| a.row_ptr[part[1]] - a.row_ptr[part[0]])
+        .max()
+        .unwrap_or(0);
+    let parts = bounds.len() - 1;
+    largest as f64 * parts as f64 / std::cmp::max(a.nnz(), 1) as f64
+}
+
+// Cuts a row-major result with `width` elements per row at the row boundaries
+fn split_rows<'a, T>(
+    mut dest: &'a mut [T],
+    width: usize,
+    bounds: &[usize],
+) -> Vec<(usize, &'a mut [T])> {
+    let mut parts = Vec::with_capacity(bounds.len());
+    for part in bounds.windows(2) {
+        let (head, tail) = dest.split_at_mut((part[1] - part[0]) * width);
+        parts.push((part[0], head));
+        dest = tail;
+    }
+    parts
+}
+
+// y = A x, every run of rows between two boundaries being a task
+pub fn spmv_par<T: Element>(a: &Csr<T>, bounds: &[usize], x: &[T], y: &mut [T]) {
+    split_rows(y, 1, bounds)
+        .into_par_iter()
+        .for_each(|(row_start, y_part)| {
+            y_part
+                .iter_mut()
+                .enumerate()
+                .for_each(|(r, y)| *y = a.row_dot(row_start + r, x));
+        });
+}
+
+// C = A B, every run of rows between two boundaries being a task
+pub fn spmm_par<T: Element>(a: &Csr<T>, bounds: &[usize], b: &[T], n: usize, c: &mut [T]) {
+    split_rows(c, n, bounds)
+        .into_par_iter()
+        .for_each(|(row_start, c_part)| {
+            c_part
+                .chunks_mut(n)
+                .enumerate()
+                .for_each(|(r, c_row)| a.row_times(row_start + r, b, n, c_row));
+        });
+}
+
+// SpMV with x all ones and SpMM with the dense k x n B, serial and split by
+// rows or by non-zeros into one part per thread.
+pub fn do_sparse_runs<T: Element>(matmul_config: &MatMulConfig, a: &Csr<T>, m_b: &[T], n: usize) {
+    assert_eq!(m_b.len(), a.cols * n);
+    let num_runs = matmul_config.num_of_runs;
+    let parts = matmul_config.num_threads as usize;
+
+    println!(
+        "Sparse A: {}x{} with {} non-zeros, longest row {}",
+        a.rows,
+        a.cols,
+        a.nnz(),
+        a.row_ptr.windows(2).map(|r| r[1] - r[0]).max().unwrap_or(0)
+    );
+
+    let x = vec![T::from_i32(1); a.cols];
+    let mut serial_y = vec![T::default(); a.rows];
+    let mut serial_c = vec![T::default(); a.rows * n];
+    let mut spmv_serial_time = Duration::new(100, 0);
+    let mut spmm_serial_time = Duration::new(100, 0);
+
+    let row_bounds = row_partition(a, parts);
+    let nnz_bounds = nnz_partition(a, parts);
+    let partitions = [("par_rows", &row_bounds), ("par_nnz", &nnz_bounds)];
+
+    if (matmul_config.code_config == 0) || (matmul_config.code_config == 2) {
+        spmv_serial_time = time_kernel("spmv_serial", num_runs, || {
+            spmv_serial(a, &x[..], &mut serial_y[..])
+        });
+        element::report_overflow("spmv_serial", &serial_y[..]);
+
+        let csc = a.to_csc();
+        let mut csc_y = vec![T::default(); a.rows];
+        time_kernel("spmv_csc_serial", num_runs, || {
+            spmv_csc_serial(&csc, &x[..], &mut csc_y[..])
+        });
+        element::assert_matches("spmv_csc_serial", &csc_y[..], &serial_y[..]);
+
+        spmm_serial_time = time_kernel("spmm_serial", num_runs, || {
+            spmm_serial(a, m_b, n, &mut serial_c[..])
+        });
+        element::report_overflow("spmm_serial", &serial_c[..]);
+        println!();
+    }
+
+    if (matmul_config.code_config == 0) || (matmul_config.code_config == 1) {
+        for (name, bounds) in partitions.iter() {
+            println!(
+                "++++ \t\t({}: largest of {} parts has {:.2}x the average non-zeros)",
+                name,
+                parts,
+                imbalance(a, bounds)
+            );
+
+            let mut y = vec![T::default(); a.rows];
+            let spmv_time = time_kernel(&format!("spmv_{}", name), num_runs, || {
+                spmv_par(a, bounds, &x[..], &mut y[..])
+            });
+            element::report_overflow(name, &y[..]);
+            if matmul_config.code_config == 0 {
+                element::assert_matches(name, &y[..], &serial_y[..]);
+                print_speedup(matmul_config, spmv_serial_time, spmv_time);
+            }
+
+            let mut c = vec![T::default(); a.rows * n];
+            let spmm_time = time_kernel(&format!("spmm_{}", name), num_runs, || {
+                spmm_par(a, bounds, m_b, n, &mut c[..])
+            });
+            element::report_overflow(name, &c[..]);
+            if matmul_config.code_config == 0 {
+                element::assert_matches(name, &c[..], &serial_c[..]);
+                print_speedup(matmul_config, spmm_serial_time, spmm_time);
+            }
+        }
+    }
+}
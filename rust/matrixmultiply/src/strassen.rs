@@ -0,0 +1,188 @@
+use crate::element::Element;
+use crate::{join4, matmulz, quarter_chunks, quarter_chunks_mut, rcopy, rmatsub, rmatsum};
+
+/*************************************
+ * Strassen multiplication
+ *************************************/
+// Both variants work on the Z-order layout of `matmulz`, where the four
+// quarters of a matrix are contiguous. The seven products of every level are
+// spawned as independent tasks, each one building its own operand sums, and
+// blocks with a side of at most `cutoff` fall back to the classic `matmulz`.
+// Rectangular products are split while every side has quarters, the padded
+// sides being powers of two, so the seven products all have the same shape.
+
+// Whether the product is small enough for the classic kernel
+fn is_base(cutoff: usize, m: usize, k: usize, n: usize) -> bool {
+    let side = m.min(k).min(n);
+    side <= cutoff || side * side <= crate::MULT_CHUNK
+}
+
+fn add<T: Element>(x: &[T], y: &[T]) -> Vec<T> {
+    let mut dest = vec![T::default(); x.len()];
+    rcopy(x, &mut dest[..]);
+    rmatsum(y, &mut dest[..]);
+    dest
+}
+
+fn sub<T: Element>(x: &[T], y: &[T]) -> Vec<T> {
+    let mut dest = vec![T::default(); x.len()];
+    rcopy(x, &mut dest[..]);
+    rmatsub(y, &mut dest[..]);
+    dest
+}
+
+// Sides (m, k, n) of an m x k times k x n product
+type Dims = (usize, usize, usize);
+
+// Either variant, called with (cutoff, m, k, n, a, b, dest)
+type Kernel<T> = fn(usize, usize, usize, usize, &[T], &[T], &mut [T]);
+
+fn mul<T: Element>(cutoff: usize, dims: Dims, x: &[T], y: &[T], kernel: Kernel<T>) -> Vec<T> {
+    let (m, k, n) = dims;
+    let mut dest = vec![T::default(); m * n];
+    kernel(cutoff, m, k, n, x, y, &mut dest[..]);
+    dest
+}
+
+// Multiply an m x k and a k x n matrix, given in the padded Z-order layout.
+pub fn strassen<T: Element>(
+    cutoff: usize,
+    m: usize,
+    k: usize,
+    n: usize,
+    a: &[T],
+    b: &[T],
+    dest: &mut [T],
+) {
+    if is_base(cutoff, m, k, n) {
+        matmulz(m, k, n, a, b, dest);
+        return;
+    }
+
+    let half = (m / 2, k / 2, n / 2);
+
+    let (a11, a12, a21, a22) = quarter_chunks(a);
+    let (b11, b12, b21, b22) = quarter_chunks(b);
+
+    let ((m1, m2, m3, m4), m5, m6, m7) = join4(
+        || {
+            join4(
+                || mul(cutoff, half, &add(a11, a22), &add(b11, b22), strassen),
+                || mul(cutoff, half, &add(a21, a22), b11, strassen),
+                || mul(cutoff, half, a11, &sub(b12, b22), strassen),
+                || mul(cutoff, half, a22, &sub(b21, b11), strassen),
+            )
+        },
+        || mul(cutoff, half, &add(a11, a12), b22, strassen),
+        || mul(cutoff, half, &sub(a21, a11), &add(b11, b12), strassen),
+        || mul(cutoff, half, &sub(a12, a22), &add(b21, b22), strassen),
+    );
+
+    let (d11, d12, d21, d22) = quarter_chunks_mut(dest);
+    join4(
+        || {
+            rcopy(&m1, d11);
+            rmatsum(&m4, d11);
+            rmatsub(&m5, d11);
+            rmatsum(&m7, d11);
+        },
+        || {
+            rcopy(&m3, d12);
+            rmatsum(&m5, d12);
+        },
+        || {
+            rcopy(&m2, d21);
+            rmatsum(&m4, d21);
+        },
+        || {
+            rcopy(&m1, d22);
+            rmatsub(&m2, d22);
+            rmatsum(&m3, d22);
+            rmatsum(&m6, d22);
+        },
+    );
+}
+
+// Winograd's form of Strassen: the operand sums build on each other and
+// P1 + P6 is shared by three quarters, which takes 16 additions against
+// Strassen's 18. The textbook form also shares U3 and U4 for 15; here every
+// quarter is summed on its own so that the four run in parallel.
+pub fn winograd<T: Element>(
+    cutoff: usize,
+    m: usize,
+    k: usize,
+    n: usize,
+    a: &[T],
+    b: &[T],
+    dest: &mut [T],
+) {
+    if is_base(cutoff, m, k, n) {
+        matmulz(m, k, n, a, b, dest);
+        return;
+    }
+
+    let half = (m / 2, k / 2, n / 2);
+
+    let (a11, a12, a21, a22) = quarter_chunks(a);
+    let (b11, b12, b21, b22) = quarter_chunks(b);
+
+    // S1 = A21 + A22, S2 = S1 - A11, S3 = A11 - A21, S4 = A12 - S2
+    // T1 = B12 - B11, T2 = B22 - T1, T3 = B22 - B12, T4 = T2 - B21
+    let ((s1, s2, s4), s3, (t1, t2, t4), t3) = join4(
+        || {
+            let s1 = add(a21, a22);
+            let s2 = sub(&s1, a11);
+            let s4 = sub(a12, &s2);
+            (s1, s2, s4)
+        },
+        || sub(a11, a21),
+        || {
+            let t1 = sub(b12, b11);
+            let t2 = sub(b22, &t1);
+            let t4 = sub(&t2, b21);
+            (t1, t2, t4)
+        },
+        || sub(b22, b12),
+    );
+
+    let ((p1, p2, p3, p4), p5, mut p6, p7) = join4(
+        || {
+            join4(
+                || mul(cutoff, half, a11, b11, winograd),
+                || mul(cutoff, half, a12, b21, winograd),
+                || mul(cutoff, half, &s4, b22, winograd),
+                || mul(cutoff, half, a22, &t4, winograd),
+            )
+        },
+        || mul(cutoff, half, &s1, &t1, winograd),
+        || mul(cutoff, half, &s2, &t2, winograd),
+        || mul(cutoff, half, &s3, &t3, winograd),
+    );
+
+    // U2 = P1 + P6 is shared by three of the quarters
+    rmatsum(&p1, &mut p6[..]);
+    let u2 = p6;
+
+    let (d11, d12, d21, d22) = quarter_chunks_mut(dest);
+    join4(
+        || {
+            rcopy(&p1, d11);
+            rmatsum(&p2, d11);
+        },
+        || {
+            rcopy(&u2, d12);
+            rmatsum(&p5, d12);
+            rmatsum(&p3, d12);
+        },
+        || {
+            rcopy(&u2, d21);
+            rmatsum(&p7, d21);
+            rmatsub(&p4, d21);
+        },
+        || {
+            rcopy(&u2, d22);
+            rmatsum(&p7, d22);
+            rmatsum(&p5, d22);
+        },
+    );
+}
@@ -0,0 +1,135 @@
+use crate::element::Element;
+use rayon::prelude::*;
+
+// Columns of the source handled by one transpose task
+const TRANSPOSE_BAND: usize = 32;
+// Side of the output blocks of the 2-D partitioned kernel
+const BLOCK_2D: usize = 64;
+
+/*************************************
+ * Multiplies on a transposed B
+ *************************************/
+// With B transposed, every element of C is the dot product of a row of A and
+// a row of B^T, so the inner loop walks both operands contiguously. The same
+// dot product is then partitioned three ways: by rows of C, by columns of C
+// and by 2-D blocks of C.
+
+// Transposes a row-major `rows` x `cols` matrix, every task writing a band of
+// rows of the result from a band of columns of the source.
+pub fn transpose_par<T: Element>(rows: usize, cols: usize, src: &[T], dest: &mut [T]) {
+    assert_eq!(src.len(), rows * cols);
+    assert_eq!(dest.len(), rows * cols);
+
+    dest.par_chunks_mut(TRANSPOSE_BAND * rows)
+        .enumerate()
+        .for_each(|(band, dest_band)| {
+            let col_start = band * TRANSPOSE_BAND;
+            let width = dest_band.len() / rows;
+
+            for (row, src_row) in src.chunks(cols).enumerate() {
+                for (col, x) in src_row[col_start..(col_start + width)].iter().enumerate() {
+                    dest_band[RM!(col, row, rows)] = *x;
+                }
+            }
+        });
+}
+
+fn dot<T: Element>(x: &[T], y: &[T]) -> T {
+    x.iter().zip(y.iter()).map(|(a, b)| *a * *b).sum()
+}
+
+// Every row of C is a task, B^T being n x k
+pub fn matmul_par_row_bt<T: Element>(
+    m: usize,
+    k: usize,
+    n: usize,
+    m_a: &[T],
+    m_bt: &[T],
+    m_c: &mut [T],
+) {
+    assert_eq!(m_a.len(), m * k);
+    assert_eq!(m_bt.len(), n * k);
+    assert_eq!(m_c.len(), m * n);
+
+    m_c.par_chunks_mut(n)
+        .zip(m_a.par_chunks(k))
+        .for_each(|(c_row, a_row)| {
+            c_row
+                .iter_mut()
+                .zip(m_bt.chunks(k))
+                .for_each(|(c, bt_row)| *c = dot(a_row, bt_row));
+        });
+}
+
+// Every column of C is a task. Columns of a row-major C are not contiguous,
+// so the result is C^T (n x m), one row per column of C.
+pub fn matmul_par_col<T: Element>(
+    m: usize,
+    k: usize,
+    n: usize,
+    m_a: &[T],
+    m_bt: &[T],
+    m_ct: &mut [T],
+) {
+    assert_eq!(m_a.len(), m * k);
+    assert_eq!(m_bt.len(), n * k);
+    assert_eq!(m_ct.len(), m * n);
+
+    m_ct.par_chunks_mut(m)
+        .zip(m_bt.par_chunks(k))
+        .for_each(|(ct_row, bt_row)| {
+            ct_row
+                .iter_mut()
+                .zip(m_a.chunks(k))
+                .for_each(|(c, a_row)| *c = dot(a_row, bt_row));
+        });
+}
+
+// Every BLOCK_2D x BLOCK_2D block of C is a task computed into its own
+// buffer, and the blocks are then gathered into C one row per task.
+pub fn matmul_par_2d<T: Element>(
+    m: usize,
+    k: usize,
+    n: usize,
+    m_a: &[T],
+    m_bt: &[T],
+    m_c: &mut [T],
+) {
+    assert_eq!(m_a.len(), m * k);
+    assert_eq!(m_bt.len(), n * k);
+    assert_eq!(m_c.len(), m * n);
+
+    let blocks_n = n.div_ceil(BLOCK_2D);
+    let num_blocks = m.div_ceil(BLOCK_2D) * blocks_n;
+
+    let blocks: Vec<Vec<T>> = (0..num_blocks)
+        .into_par_iter()
+        .map(|block| {
+            let row_start = (block / blocks_n) * BLOCK_2D;
+            let col_start = (block % blocks_n) * BLOCK_2D;
+            let row_end = std::cmp::min(row_start + BLOCK_2D, m);
+            let col_end = std::cmp::min(col_start + BLOCK_2D, n);
+
+            let mut dest = Vec::with_capacity((row_end - row_start) * (col_end - col_start));
+            for a_row in m_a[(row_start * k)..(row_end * k)].chunks(k) {
+                for bt_row in m_bt[(col_start * k)..(col_end * k)].chunks(k) {
+                    dest.push(dot(a_row, bt_row));
+                }
+            }
+            dest
+        })
+        .collect();
+
+    m_c.par_chunks_mut(n).enumerate().for_each(|(row, c_row)| {
+        let (block_row, r) = (row / BLOCK_2D, row % BLOCK_2D);
+
+        c_row
+            .chunks_mut(BLOCK_2D)
+            .enumerate()
+            .for_each(|(block_col, c_part)| {
+                let width = c_part.len();
+                let block = &blocks[block_row * blocks_n + block_col];
+                c_part.copy_from_slice(&block[(r * width)..((r + 1) * width)]);
+            });
+    });
+}
@@ -0,0 +1,140 @@
+use crate::element::Element;
+use crate::MULT_CHUNK;
+use rayon::prelude::*;
+
+/*************************************
+ * Z-order (Morton) layout
+ *************************************/
+// `matmulz` recurses on contiguous quarters (top-left, top-right, bottom-left,
+// bottom-right) and stops at MULT_CHUNK elements, where it hands the block to
+// `matmul_serial`. The layout that matches is therefore a Z-order of square
+// tiles of MULT_CHUNK elements, each tile stored in row-major order. Rows and
+// columns are padded with zeros to a power of two each.
+//
+// A rectangular matrix only has quarters while both of its sides are longer
+// than a tile. Past that point the longer side keeps being halved alone, so
+// the tile index is the Morton code of the leading bits of the tile
+// coordinates followed by the remaining bits of the longer side.
+
+// Padded length of one side of a matrix used by `matmulz`
+pub fn zorder_size(size: usize) -> usize {
+    size.next_power_of_two()
+}
+
+// Length of the row-major tiles at the leaves of the recursion along a side
+// padded to `padded_size`
+pub fn zorder_tile(padded_size: usize) -> usize {
+    assert!(MULT_CHUNK.is_power_of_two() && MULT_CHUNK.trailing_zeros().is_multiple_of(2));
+    std::cmp::min(padded_size, 1 << (MULT_CHUNK.trailing_zeros() / 2))
+}
+
+// Interleaves the bits of the tile coordinates, the row bit being the most
+// significant one of every pair.
+fn morton_encode(row: usize, col: usize) -> usize {
+    let mut index = 0;
+    for bit in 0..(usize::BITS / 2) {
+        index |= ((col >> bit) & 1) << (2 * bit);
+        index |= ((row >> bit) & 1) << (2 * bit + 1);
+    }
+    index
+}
+
+fn morton_decode(index: usize) -> (usize, usize) {
+    let mut row = 0;
+    let mut col = 0;
+    for bit in 0..(usize::BITS / 2) {
+        col |= ((index >> (2 * bit)) & 1) << bit;
+        row |= ((index >> (2 * bit + 1)) & 1) << bit;
+    }
+    (row, col)
+}
+
+// Position of a tile in a grid of 2^row_bits x 2^col_bits tiles
+fn tile_index(row_bits: u32, col_bits: u32, tile_row: usize, tile_col: usize) -> usize {
+    let shared = std::cmp::min(row_bits, col_bits);
+    let (row_rest, col_rest) = (row_bits - shared, col_bits - shared);
+    let rest = (tile_row & ((1 << row_rest) - 1)) | (tile_col & ((1 << col_rest) - 1));
+    (morton_encode(tile_row >> row_rest, tile_col >> col_rest) << (row_rest + col_rest)) | rest
+}
+
+fn tile_coords(row_bits: u32, col_bits: u32, index: usize) -> (usize, usize) {
+    let shared = std::cmp::min(row_bits, col_bits);
+    let (row_rest, col_rest) = (row_bits - shared, col_bits - shared);
+    let rest = index & ((1 << (row_rest + col_rest)) - 1);
+    let (row, col) = morton_decode(index >> (row_rest + col_rest));
+    (
+        (row << row_rest) | (rest & ((1 << row_rest) - 1)),
+        (col << col_rest) | (rest & ((1 << col_rest) - 1)),
+    )
+}
+
+// Padded sides, tile sides and tile grid bits of a `rows` x `cols` matrix
+fn layout(rows: usize, cols: usize) -> (usize, usize, usize, usize, u32, u32) {
+    let (padded_rows, padded_cols) = (zorder_size(rows), zorder_size(cols));
+    let (tile_rows, tile_cols) = (zorder_tile(padded_rows), zorder_tile(padded_cols));
+    (
+        padded_rows,
+        padded_cols,
+        tile_rows,
+        tile_cols,
+        (padded_rows / tile_rows).trailing_zeros(),
+        (padded_cols / tile_cols).trailing_zeros(),
+    )
+}
+
+// Converts a row-major `rows` x `cols` matrix into the padded Z-order layout,
+// one tile per task.
+pub fn to_zorder<T: Element>(rows: usize, cols: usize, src: &[T], dest: &mut [T]) {
+    let (padded_rows, padded_cols, tile_rows, tile_cols, row_bits, col_bits) = layout(rows, cols);
+    assert_eq!(src.len(), rows * cols);
+    assert_eq!(dest.len(), padded_rows * padded_cols);
+
+    dest.par_chunks_mut(tile_rows * tile_cols)
+        .enumerate()
+        .for_each(|(z, dest_tile)| {
+            let (tile_row, tile_col) = tile_coords(row_bits, col_bits, z);
+            let col = tile_col * tile_cols;
+
+            dest_tile
+                .chunks_mut(tile_cols)
+                .enumerate()
+                .for_each(|(r, dest_row)| {
+                    let row = tile_row * tile_rows + r;
+                    let len = if row < rows && col < cols {
+                        std::cmp::min(tile_cols, cols - col)
+                    } else {
+                        0
+                    };
+
+                    if len > 0 {
+                        let start = RM!(row, col, cols);
+                        dest_row[..len].copy_from_slice(&src[start..(start + len)]);
+                    }
+                    dest_row[len..].iter_mut().for_each(|d| *d = T::default());
+                });
+        });
+}
+
+// Converts the padded Z-order layout back into a row-major `rows` x `cols`
+// matrix, one row per task.
+pub fn from_zorder<T: Element>(rows: usize, cols: usize, src: &[T], dest: &mut [T]) {
+    let (padded_rows, padded_cols, tile_rows, tile_cols, row_bits, col_bits) = layout(rows, cols);
+    assert_eq!(src.len(), padded_rows * padded_cols);
+    assert_eq!(dest.len(), rows * cols);
+
+    dest.par_chunks_mut(cols)
+        .enumerate()
+        .for_each(|(row, dest_row)| {
+            let (tile_row, r) = (row / tile_rows, row % tile_rows);
+
+            dest_row
+                .chunks_mut(tile_cols)
+                .enumerate()
+                .for_each(|(tile_col, dest_part)| {
+                    let start =
+                        tile_index(row_bits, col_bits, tile_row, tile_col) * tile_rows * tile_cols
+                            + r * tile_cols;
+                    dest_part.copy_from_slice(&src[start..(start + dest_part.len())]);
+                });
+        });
+}
@@ -0,0 +1,366 @@
+use crate::quicksort::insertion_sort;
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+// Elements sorted by one small-block sort
+const SMALL_BLOCK: usize = 16;
+
+// Merges of blocks at most this long are done by one task per block
+const LOCAL_BLOCK: usize = 1 << 12;
+
+/*************************************
+ * Bitonic sort
+ *************************************/
+// A sorting network: the input is padded to a power of two N and sorted by
+// log N rounds of merges, the merge of size k being a flip stage, which
+// compares element i of every block of k with element k - 1 - i, followed by
+// half-cleaner stages, which compare i with i + k/4, i + k/8, ..., i + 1.
+// Every stage is a fixed set of independent compare-exchanges whatever the
+// data, so the stages are split statically between the threads: the work of
+// a stage is cut into one run of equal length per thread, spawned in a
+// `rayon::scope`, instead of being left to work stealing. With the flip stage
+// all the merges sort in the same direction and the padding stays at the end;
+// a compare-exchange with a padding element never swaps, so the padding is
+// never stored and those compare-exchanges are skipped.
+//
+// Blocks of SMALL_BLOCK elements are sorted first, in AVX2 registers when the
+// elements have an in-register sort and the CPU has AVX2. Merges that stay
+// inside a block of LOCAL_BLOCK elements are done by one task per block, so
+// that only the stages with a longer stride go over the whole input.
+
+// Sorts a block of SMALL_BLOCK elements in ascending order
+pub type SmallSort<T> = fn(&mut [T]);
+
+pub trait BitonicElement: Ord + Send + Sync + Sized {
+    fn small_sort() -> Option<SmallSort<Self>> {
+        None
+    }
+}
+
+impl BitonicElement for u64 {
+    fn small_sort() -> Option<SmallSort<u64>> {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                return Some(sort16_u64_avx2);
+            }
+        }
+        None
+    }
+}
+
+fn compare_exchange<T, F>(a: &mut T, b: &mut T, is_less: &F)
+where
+    F: Fn(&T, &T) -> bool,
+{
+    if is_less(b, a) {
+        std::mem::swap(a, b);
+    }
+}
+
+// Runs `f` on every item, the items being cut into one run of consecutive
+// items per thread
+fn for_each_static<I, F>(items: Vec<I>, f: &F)
+where
+    I: Send,
+    F: Fn(I) + Sync,
+{
+    let threads = rayon::current_num_threads();
+    let per_thread = std::cmp::max(1, items.len().div_ceil(threads));
+    let mut items = items.into_iter();
+
+    rayon::scope(|s| {
+        for _ in 0..threads {
+            let run: Vec<I> = items.by_ref().take(per_thread).collect();
+            if run.is_empty() {
+                break;
+            }
+            s.spawn(move |_| run.into_iter().for_each(f));
+        }
+    });
+}
+
+// Element i of `lo` against element i of `hi`, or of `hi` reversed for a flip
+fn exchange_runs<T, F>(lo: &mut [T], hi: &mut [T], flip: bool, is_less: &F)
+where
+    F: Fn(&T, &T) -> bool,
+{
+    if flip {
+        lo.iter_mut()
+            .zip(hi.iter_mut().rev())
+            .for_each(|(a, b)| compare_exchange(a, b, is_less));
+    } else {
+        lo.iter_mut()
+            .zip(hi.iter_mut())
+            .for_each(|(a, b)| compare_exchange(a, b, is_less));
+    }
+}
+
+// Element i of the first half against element 2 * half - 1 - i, in a block
+// that may be cut short by the padding
+fn flip_stage<T, F>(block: &mut [T], half: usize, is_less: &F)
+where
+    F: Fn(&T, &T) -> bool,
+{
+    if block.len() <= half {
+        return;
+    }
+    let (lo, hi) = block.split_at_mut(half);
+    exchange_runs(&mut lo[(half - hi.len())..], hi, true, is_less);
+}
+
+// Element i of the first half against element half + i
+fn half_clean_stage<T, F>(block: &mut [T], half: usize, is_less: &F)
+where
+    F: Fn(&T, &T) -> bool,
+{
+    if block.len() <= half {
+        return;
+    }
+    let (lo, hi) = block.split_at_mut(half);
+    exchange_runs(lo, hi, false, is_less);
+}
+
+// A flip or half-cleaner stage over all the blocks of `2 * half`, in parallel.
+// The halves of every block are cut into matching runs, enough of them for
+// every thread to get some when there are fewer blocks than threads.
+fn par_stage<T, F>(v: &mut [T], half: usize, flip: bool, is_less: &F)
+where
+    T: Send,
+    F: Fn(&T, &T) -> bool + Sync,
+{
+    let num_blocks = v.len().div_ceil(2 * half);
+    let parts = rayon::current_num_threads().div_ceil(num_blocks);
+    let mut pairs: Vec<(&mut [T], &mut [T])> = Vec::with_capacity(num_blocks * parts);
+
+    for block in v.chunks_mut(2 * half) {
+        if block.len() <= half {
+            continue;
+        }
+        let (lo, hi) = block.split_at_mut(half);
+        let part_len = hi.len().div_ceil(parts);
+        if flip {
+            let lo = &mut lo[(half - hi.len())..];
+            pairs.extend(lo.chunks_mut(part_len).zip(hi.rchunks_mut(part_len)));
+        } else {
+            let lo = &mut lo[..hi.len()];
+            pairs.extend(lo.chunks_mut(part_len).zip(hi.chunks_mut(part_len)));
+        }
+    }
+
+    for_each_static(pairs, &|(lo, hi): (&mut [T], &mut [T])| {
+        exchange_runs(lo, hi, flip, is_less)
+    });
+}
+
+// The half-cleaners of strides `half` down to 1, inside one block
+fn local_clean<T, F>(block: &mut [T], mut half: usize, is_less: &F)
+where
+    F: Fn(&T, &T) -> bool,
+{
+    while half >= 1 {
+        block
+            .chunks_mut(2 * half)
+            .for_each(|sub| half_clean_stage(sub, half, is_less));
+        half /= 2;
+    }
+}
+
+// All the merges up to LOCAL_BLOCK inside one block
+fn local_sort<T, F>(
+    block: &mut [T],
+    is_less: &F,
+    small_sort: Option<SmallSort<T>>,
+    descending: bool,
+) where
+    F: Fn(&T, &T) -> bool,
+{
+    for small in block.chunks_mut(SMALL_BLOCK) {
+        match small_sort {
+            Some(sort) if small.len() == SMALL_BLOCK => {
+                sort(small);
+                if descending {
+                    small.reverse();
+                }
+            }
+            _ => insertion_sort(small, is_less),
+        }
+    }
+
+    let mut k = 2 * SMALL_BLOCK;
+    while k <= LOCAL_BLOCK && k / 2 < block.len() {
+        block
+            .chunks_mut(k)
+            .for_each(|sub| flip_stage(sub, k / 2, is_less));
+        local_clean(block, k / 4, is_less);
+        k *= 2;
+    }
+}
+
+fn bitonic_sort<T: BitonicElement>(v: &mut [T], descending: bool, parallel: bool) {
+    let is_less = |a: &T, b: &T| if descending { b < a } else { a < b };
+    let small_sort = T::small_sort();
+
+    if parallel {
+        for_each_static(v.chunks_mut(LOCAL_BLOCK).collect(), &|block: &mut [T]| {
+            local_sort(block, &is_less, small_sort, descending)
+        });
+    } else {
+        v.chunks_mut(LOCAL_BLOCK)
+            .for_each(|block| local_sort(block, &is_less, small_sort, descending));
+    }
+
+    // The merges longer than a local block
+    let mut k = 2 * LOCAL_BLOCK;
+    while k / 2 < v.len() {
+        let mut half = k / 2;
+        if parallel {
+            par_stage(v, half, true, &is_less);
+        } else {
+            v.chunks_mut(k)
+                .for_each(|block| flip_stage(block, half, &is_less));
+        }
+        half /= 2;
+
+        while half >= LOCAL_BLOCK {
+            if parallel {
+                par_stage(v, half, false, &is_less);
+            } else {
+                v.chunks_mut(2 * half)
+                    .for_each(|block| half_clean_stage(block, half, &is_less));
+            }
+            half /= 2;
+        }
+
+        if parallel {
+            for_each_static(v.chunks_mut(LOCAL_BLOCK).collect(), &|block: &mut [T]| {
+                local_clean(block, half, &is_less)
+            });
+        } else {
+            v.chunks_mut(LOCAL_BLOCK)
+                .for_each(|block| local_clean(block, half, &is_less));
+        }
+        k *= 2;
+    }
+}
+
+pub fn bitonic_sort_serial<T: BitonicElement>(v: &mut [T], descending: bool) {
+    bitonic_sort(v, descending, false);
+}
+
+pub fn bitonic_sort_par<T: BitonicElement>(v: &mut [T], descending: bool) {
+    bitonic_sort(v, descending, true);
+}
+
+/*************************************
+ * In-register sort of 16 u64
+ *************************************/
+// The 16 elements are four ymm registers of four lanes. A sorting network
+// across the registers sorts the four columns, a 4 x 4 transpose turns them
+// into four sorted runs, and two rounds of bitonic merges, with the reversal
+// and the strides inside a register done by lane permutes, merge the runs.
+// AVX2 only compares signed 64-bit integers, so the sign bit is flipped on
+// load and store.
+
+#[cfg(target_arch = "x86_64")]
+fn sort16_u64_avx2(block: &mut [u64]) {
+    assert_eq!(block.len(), SMALL_BLOCK);
+    // Only selected after checking for AVX2
+    unsafe { sort16_u64_avx2_inner(block.as_mut_ptr()) }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn min_max(a: __m256i, b: __m256i) -> (__m256i, __m256i) {
+    let greater = _mm256_cmpgt_epi64(a, b);
+    (
+        _mm256_blendv_epi8(a, b, greater),
+        _mm256_blendv_epi8(b, a, greater),
+    )
+}
+
+// Sorts the four lanes of a bitonic register
+#[cfg(target_arch = "x86_64")]
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn clean_lanes(x: __m256i) -> __m256i {
+    let (lo, hi) = min_max(x, _mm256_permute4x64_epi64(x, 0x4E));
+    let x = _mm256_blend_epi32(lo, hi, 0xF0);
+    let (lo, hi) = min_max(x, _mm256_permute4x64_epi64(x, 0xB1));
+    _mm256_blend_epi32(lo, hi, 0xCC)
+}
+
+// Sorts a bitonic sequence held in a power of two of registers
+#[cfg(target_arch = "x86_64")]
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn clean_registers(r: &mut [__m256i]) {
+    let mut stride = r.len() / 2;
+    while stride >= 1 {
+        for i in (0..r.len()).filter(|i| i & stride == 0) {
+            let (lo, hi) = min_max(r[i], r[i + stride]);
+            r[i] = lo;
+            r[i + stride] = hi;
+        }
+        stride /= 2;
+    }
+    for x in r.iter_mut() {
+        *x = clean_lanes(*x);
+    }
+}
+
+// Merges the sorted runs held in the two halves of the registers
+#[cfg(target_arch = "x86_64")]
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn merge_registers(r: &mut [__m256i]) {
+    let m = r.len() / 2;
+    for i in 0..m {
+        let reversed = _mm256_permute4x64_epi64(r[2 * m - 1 - i], 0x1B);
+        let (lo, hi) = min_max(r[i], reversed);
+        r[i] = lo;
+        r[2 * m - 1 - i] = hi;
+    }
+    // The upper half was filled from the end
+    r[m..].reverse();
+    let (lo, hi) = r.split_at_mut(m);
+    clean_registers(lo);
+    clean_registers(hi);
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn sort16_u64_avx2_inner(p: *mut u64) {
+    let sign = _mm256_set1_epi64x(i64::MIN);
+    let mut r = [_mm256_setzero_si256(); 4];
+    for (i, x) in r.iter_mut().enumerate() {
+        *x = _mm256_xor_si256(_mm256_loadu_si256(p.add(4 * i) as *const __m256i), sign);
+    }
+
+    // Sort the columns
+    for &(a, b) in [(0, 1), (2, 3), (0, 2), (1, 3), (1, 2)].iter() {
+        let (lo, hi) = min_max(r[a], r[b]);
+        r[a] = lo;
+        r[b] = hi;
+    }
+
+    // Transpose, every register then holds a sorted run
+    let t0 = _mm256_unpacklo_epi64(r[0], r[1]);
+    let t1 = _mm256_unpackhi_epi64(r[0], r[1]);
+    let t2 = _mm256_unpacklo_epi64(r[2], r[3]);
+    let t3 = _mm256_unpackhi_epi64(r[2], r[3]);
+    r[0] = _mm256_permute2x128_si256(t0, t2, 0x20);
+    r[1] = _mm256_permute2x128_si256(t1, t3, 0x20);
+    r[2] = _mm256_permute2x128_si256(t0, t2, 0x31);
+    r[3] = _mm256_permute2x128_si256(t1, t3, 0x31);
+
+    merge_registers(&mut r[0..2]);
+    merge_registers(&mut r[2..4]);
+    merge_registers(&mut r[..]);
+
+    for (i, x) in r.iter().enumerate() {
+        _mm256_storeu_si256(p.add(4 * i) as *mut __m256i, _mm256_xor_si256(*x, sign));
+    }
+}
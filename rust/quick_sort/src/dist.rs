@@ -0,0 +1,110 @@
+use rand::distributions::{Distribution, Normal};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+// Elements generated by one task, each with its own generator
+const GEN_CHUNK: usize = 1 << 16;
+
+// Distinct values of the few-unique distribution
+const FEW_UNIQUE: u64 = 16;
+
+/*************************************
+ * Input distributions
+ *************************************/
+// Every chunk of GEN_CHUNK elements is generated by its own task from a
+// generator seeded with --seed plus the index of the chunk, so the input
+// only depends on the seed and not on the number of threads. The values are
+// keys of `bits` bits, which the element types turn into elements.
+
+#[derive(Copy, Clone, Debug)]
+pub enum Dist {
+    Uniform,
+    Sorted,
+    Reverse,
+    NearlySorted,
+    OrganPipe,
+    FewUnique,
+    AllEqual,
+    Zipf,
+    Gaussian,
+}
+
+pub const DIST_NAMES: [&str; 9] = [
+    "uniform",
+    "sorted",
+    "reverse",
+    "nearly-sorted",
+    "organ-pipe",
+    "few-unique",
+    "all-equal",
+    "zipf",
+    "gaussian",
+];
+
+impl Dist {
+    pub fn from_name(name: &str) -> Dist {
+        match name {
+            "sorted" => Dist::Sorted,
+            "reverse" => Dist::Reverse,
+            "nearly-sorted" => Dist::NearlySorted,
+            "organ-pipe" => Dist::OrganPipe,
+            "few-unique" => Dist::FewUnique,
+            "all-equal" => Dist::AllEqual,
+            "zipf" => Dist::Zipf,
+            "gaussian" => Dist::Gaussian,
+            _ => Dist::Uniform,
+        }
+    }
+}
+
+// Key i of n, for the distributions that draw numbers
+fn element(dist: Dist, i: u64, n: u64, bits: u32, rng: &mut StdRng, normal: &Normal) -> u64 {
+    match dist {
+        Dist::Uniform => rng.gen::<u64>() >> (64 - bits),
+        Dist::Sorted | Dist::NearlySorted => i,
+        Dist::Reverse => n - i,
+        Dist::OrganPipe => std::cmp::min(i, n - i),
+        Dist::FewUnique => rng.gen_range(0, FEW_UNIQUE),
+        Dist::AllEqual => 42,
+        // Rank k with probability about 1 / k: k = n^u for u uniform in [0, 1)
+        Dist::Zipf => (n as f64).powf(rng.gen::<f64>()) as u64,
+        // Clamped to the key range, which is 16 standard deviations away
+        Dist::Gaussian => {
+            let max = (u64::MAX >> (64 - bits)) as f64;
+            normal.sample(rng).max(0.0).min(max) as u64
+        }
+    }
+}
+
+// `n` keys of `dist`; nearly-sorted inputs are sorted with `swaps` random
+// pairs of keys exchanged
+pub fn generate(dist: Dist, n: usize, bits: u32, seed: u64, swaps: u64) -> Vec<u64> {
+    let normal = Normal::new(2_f64.powi(bits as i32 - 1), 2_f64.powi(bits as i32 - 5));
+    let mut v = vec![0; n];
+
+    v.par_chunks_mut(GEN_CHUNK)
+        .enumerate()
+        .for_each(|(c, part)| {
+            let mut rng = StdRng::seed_from_u64(seed.wrapping_add(c as u64));
+            part.iter_mut().enumerate().for_each(|(i, x)| {
+                *x = element(
+                    dist,
+                    (c * GEN_CHUNK + i) as u64,
+                    n as u64,
+                    bits,
+                    &mut rng,
+                    &normal,
+                )
+            });
+        });
+
+    if let Dist::NearlySorted = dist {
+        let mut rng = StdRng::seed_from_u64(seed.wrapping_sub(1));
+        for _ in 0..swaps {
+            v.swap(rng.gen_range(0, n), rng.gen_range(0, n));
+        }
+    }
+
+    v
+}
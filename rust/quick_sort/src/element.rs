@@ -0,0 +1,203 @@
+use crate::bitonic::BitonicElement;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+
+// Words of payload carried by a record, making it 64 bytes
+const PAYLOAD_WORDS: usize = 7;
+
+// Suffixes appended to the string keys, so that their lengths vary
+const SUFFIX: &str = "abcdefghijklmnopq";
+
+/*************************************
+ * Element types
+ *************************************/
+// Every type sorted by the benchmark is built from a key of the input
+// distribution and the index of the element: u32 and u64 take the key as it
+// is, f64 a signed value in the same order, records carry the index in their
+// payload and strings are the key in decimal followed by a suffix of a length
+// chosen by the key. The fingerprint of an element covers all of it, payload
+// included, for the permutation check of --verify.
+
+pub trait SortElement: BitonicElement + Clone + Default {
+    // Bits of the keys drawn for this type
+    const KEY_BITS: u32 = 64;
+
+    fn from_key(key: u64, index: usize) -> Self;
+
+    fn fingerprint(&self) -> u64;
+}
+
+impl BitonicElement for u32 {}
+
+impl SortElement for u32 {
+    const KEY_BITS: u32 = 32;
+
+    fn from_key(key: u64, _: usize) -> u32 {
+        key as u32
+    }
+
+    fn fingerprint(&self) -> u64 {
+        *self as u64
+    }
+}
+
+impl SortElement for u64 {
+    fn from_key(key: u64, _: usize) -> u64 {
+        key
+    }
+
+    fn fingerprint(&self) -> u64 {
+        *self
+    }
+}
+
+// f64 ordered by IEEE 754 totalOrder, which also orders NaNs
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TotalF64(pub f64);
+
+impl PartialEq for TotalF64 {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for TotalF64 {}
+
+impl PartialOrd for TotalF64 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TotalF64 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl BitonicElement for TotalF64 {}
+
+// Keys of 53 bits are exact in f64, so every key keeps its own value, and
+// shifting them down by 2^52 puts half of them below zero
+impl SortElement for TotalF64 {
+    const KEY_BITS: u32 = 53;
+
+    fn from_key(key: u64, _: usize) -> TotalF64 {
+        TotalF64(key as f64 - 2_f64.powi(52))
+    }
+
+    fn fingerprint(&self) -> u64 {
+        self.0.to_bits()
+    }
+}
+
+// A key and its payload, compared by key only
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Record {
+    pub key: u64,
+    // Moved with the key, never compared
+    pub payload: [u64; PAYLOAD_WORDS],
+}
+
+impl PartialEq for Record {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for Record {}
+
+impl PartialOrd for Record {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Record {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+impl BitonicElement for Record {}
+
+impl SortElement for Record {
+    fn from_key(key: u64, index: usize) -> Record {
+        Record {
+            key,
+            payload: [index as u64; PAYLOAD_WORDS],
+        }
+    }
+
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        hasher.write_u64(self.key);
+        self.payload.iter().for_each(|&word| hasher.write_u64(word));
+        hasher.finish()
+    }
+}
+
+impl BitonicElement for String {}
+
+// Zero padded, so that the strings are in the order of their keys
+impl SortElement for String {
+    fn from_key(key: u64, _: usize) -> String {
+        let suffix = &SUFFIX[..(key % (SUFFIX.len() as u64 + 1)) as usize];
+        format!("{:020}{}", key, suffix)
+    }
+
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(self.as_bytes());
+        hasher.finish()
+    }
+}
+
+// An element and its index in the input, compared by element only, so that
+// a stable sort leaves equal elements in the order of their indices
+#[derive(Clone, Debug, Default)]
+pub struct Tagged<T> {
+    pub value: T,
+    pub index: usize,
+}
+
+impl<T: Ord> PartialEq for Tagged<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T: Ord> Eq for Tagged<T> {}
+
+impl<T: Ord> PartialOrd for Tagged<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Ord> Ord for Tagged<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.value.cmp(&other.value)
+    }
+}
+
+impl<T: SortElement> BitonicElement for Tagged<T> {}
+
+impl<T: SortElement> SortElement for Tagged<T> {
+    const KEY_BITS: u32 = T::KEY_BITS;
+
+    fn from_key(key: u64, index: usize) -> Tagged<T> {
+        Tagged {
+            value: T::from_key(key, index),
+            index,
+        }
+    }
+
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        hasher.write_u64(self.value.fingerprint());
+        hasher.write_usize(self.index);
+        hasher.finish()
+    }
+}
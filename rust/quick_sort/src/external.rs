@@ -0,0 +1,642 @@
+use crate::{as_ms, best_run, dist, verify, QSConfig};
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BinaryHeap;
+use std::fs::{self, File, OpenOptions};
+use std::hash::Hasher;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// Bytes of the key at the start of every record
+const KEY_BYTES: usize = 8;
+
+// Keys of every run kept in memory to pick the splitters of the merge
+const RUN_SAMPLES: usize = 256;
+
+// Smallest buffer of a run or of the output in the merge
+const MIN_BUFFER: usize = 1 << 16;
+
+// Run files open at once in a merge, over all the parts, well under the usual
+// limit of 1024 file descriptors
+const MAX_OPEN_FILES: usize = 512;
+
+/*************************************
+ * External sort
+ *************************************/
+// Sorts a binary file of fixed-width records, each starting with a u64
+// little-endian key, within a memory budget, in two passes over the data.
+//
+// Run formation: the file is read in chunks as large as the budget allows.
+// The keys of a chunk are sorted with par_sort_unstable along with the index
+// of their record, the records are gathered in that order in parallel and the
+// chunk is written out as a run to a temporary file. Every run keeps an evenly
+// spaced sample of its keys.
+//
+// Merge: the sorted samples of all the runs give one splitter per thread, and
+// the part of the output between two splitters takes from every run the
+// records with a key in between, found by binary search in the run files.
+// The parts are merged in parallel, each with a heap over its runs, reading
+// and writing through buffers sized from its share of the budget, at its own
+// offset in the output file. Records with a key equal to a splitter all go to
+// the same part, so inputs with few distinct keys give unbalanced parts.
+// Every part keeps a file open per run, so with more runs than fit under
+// MAX_OPEN_FILES between the parts, groups of runs are first merged the same
+// way into longer runs, pass after pass, until one pass can take them all.
+//
+// The time of every pass is split between reading, writing and computing;
+// the times of the merge are summed over the parts, which run concurrently.
+// Descending sorts compare the complements of the keys.
+
+#[derive(Copy, Clone, Default)]
+struct PassTimes {
+    read: Duration,
+    compute: Duration,
+    write: Duration,
+}
+
+impl PassTimes {
+    fn add(self, other: PassTimes) -> PassTimes {
+        PassTimes {
+            read: self.read + other.read,
+            compute: self.compute + other.compute,
+            write: self.write + other.write,
+        }
+    }
+}
+
+// Times of one external sort
+struct SortTimes {
+    runs: PassTimes,
+    runs_wall: Duration,
+    merge: PassTimes,
+    merge_wall: Duration,
+    num_runs: usize,
+    merge_passes: usize,
+}
+
+// A sorted run in a temporary file
+struct Run {
+    path: PathBuf,
+    len: usize,
+    sample: Vec<u64>,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn key_of(record: &[u8]) -> u64 {
+    let mut key = [0; KEY_BYTES];
+    key.copy_from_slice(&record[..KEY_BYTES]);
+    u64::from_le_bytes(key)
+}
+
+// The key to sort by in ascending order
+fn order_key(record: &[u8], descending: bool) -> u64 {
+    if descending {
+        !key_of(record)
+    } else {
+        key_of(record)
+    }
+}
+
+fn record_fingerprint(record: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(record);
+    hasher.finish()
+}
+
+// Reads until `buf` is full or the end of the file, returns the bytes read
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            bytes => filled += bytes,
+        }
+    }
+    Ok(filled)
+}
+
+fn temp_path(qs_config: &QSConfig, name: &str) -> PathBuf {
+    let tmp_dir = match &qs_config.tmp_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::temp_dir(),
+    };
+    tmp_dir.join(format!("quick_sort-{}-{}.bin", std::process::id(), name))
+}
+
+// Records held in memory at once, with their sorted copy and their keys
+fn chunk_records(qs_config: &QSConfig) -> usize {
+    let memory = (qs_config.memory_mb as usize) << 20;
+    std::cmp::max(1, memory / (2 * qs_config.record_size + 16))
+}
+
+fn form_runs(
+    qs_config: &QSConfig,
+    input: &str,
+    runs: &mut Vec<Run>,
+    times: &mut PassTimes,
+) -> io::Result<()> {
+    let record_size = qs_config.record_size;
+    let descending = qs_config.sort_order == 1;
+    let chunk_bytes = chunk_records(qs_config) * record_size;
+
+    let mut file = File::open(input)?;
+    let mut chunk = vec![0; chunk_bytes];
+    let mut sorted = vec![0; chunk_bytes];
+    let mut keys: Vec<(u64, usize)> = Vec::with_capacity(chunk_bytes / record_size);
+
+    loop {
+        let start = Instant::now();
+        let bytes = read_full(&mut file, &mut chunk)?;
+        times.read += start.elapsed();
+        if bytes == 0 {
+            break;
+        }
+        if bytes % record_size != 0 {
+            return Err(invalid(format!(
+                "{} is not made of records of {} bytes",
+                input, record_size
+            )));
+        }
+
+        let start = Instant::now();
+        let len = bytes / record_size;
+        keys.clear();
+        keys.par_extend(
+            chunk[..bytes]
+                .par_chunks(record_size)
+                .enumerate()
+                .map(|(i, record)| (order_key(record, descending), i)),
+        );
+        keys.par_sort_unstable_by_key(|&(key, _)| key);
+        sorted[..bytes]
+            .par_chunks_mut(record_size)
+            .zip(keys.par_iter())
+            .for_each(|(dest, &(_, i))| {
+                dest.copy_from_slice(&chunk[i * record_size..(i + 1) * record_size])
+            });
+        let samples = std::cmp::min(RUN_SAMPLES, len);
+        let sample = (0..samples)
+            .map(|s| keys[(2 * s + 1) * len / (2 * samples)].0)
+            .collect();
+        times.compute += start.elapsed();
+
+        let start = Instant::now();
+        let path = temp_path(qs_config, &format!("run{}", runs.len()));
+        let mut file = File::create(&path)?;
+        runs.push(Run { path, len, sample });
+        file.write_all(&sorted[..bytes])?;
+        times.write += start.elapsed();
+    }
+
+    Ok(())
+}
+
+fn sorted_samples(runs: &[Run]) -> Vec<u64> {
+    let mut sample: Vec<u64> = runs
+        .iter()
+        .flat_map(|run| run.sample.iter().cloned())
+        .collect();
+    sample.sort_unstable();
+    sample
+}
+
+// Splitters at regular ranks of the samples of all the runs
+fn choose_splitters(runs: &[Run], parts: usize) -> Vec<u64> {
+    let sample = sorted_samples(runs);
+    (1..parts)
+        .map(|q| sample[q * sample.len() / parts])
+        .collect()
+}
+
+// The sample of the run merged from `runs`, evenly spaced in theirs
+fn merged_sample(runs: &[Run]) -> Vec<u64> {
+    let sample = sorted_samples(runs);
+    let samples = std::cmp::min(RUN_SAMPLES, sample.len());
+    (0..samples)
+        .map(|s| sample[(2 * s + 1) * sample.len() / (2 * samples)])
+        .collect()
+}
+
+// Records of a run with a key below `splitter`, by binary search in its file
+fn lower_bound(
+    file: &mut File,
+    run: &Run,
+    record_size: usize,
+    splitter: u64,
+    descending: bool,
+) -> io::Result<usize> {
+    let mut key = [0; KEY_BYTES];
+    let (mut lo, mut hi) = (0, run.len);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        file.seek(SeekFrom::Start((mid * record_size) as u64))?;
+        file.read_exact(&mut key)?;
+        if order_key(&key, descending) < splitter {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    Ok(lo)
+}
+
+// Reads the records of a range of a run through a buffer
+struct RunReader {
+    file: File,
+    buf: Vec<u8>,
+    pos: usize,
+    end: usize,
+    remaining: usize,
+}
+
+impl RunReader {
+    fn open(
+        path: &Path,
+        first: usize,
+        last: usize,
+        record_size: usize,
+        buffer: usize,
+    ) -> io::Result<RunReader> {
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start((first * record_size) as u64))?;
+        let buf_records = std::cmp::max(1, buffer / record_size);
+        let mut reader = RunReader {
+            file,
+            buf: vec![0; buf_records * record_size],
+            pos: 0,
+            end: 0,
+            remaining: last - first,
+        };
+        reader.refill(record_size)?;
+        Ok(reader)
+    }
+
+    fn refill(&mut self, record_size: usize) -> io::Result<()> {
+        let records = std::cmp::min(self.remaining, self.buf.len() / record_size);
+        self.file
+            .read_exact(&mut self.buf[..records * record_size])?;
+        self.pos = 0;
+        self.end = records * record_size;
+        self.remaining -= records;
+        Ok(())
+    }
+
+    fn current(&self, record_size: usize) -> &[u8] {
+        &self.buf[self.pos..self.pos + record_size]
+    }
+}
+
+// Merges the records of every run between bounds[j].0 and bounds[j].1 into
+// the output, starting at record `offset`
+fn merge_part(
+    qs_config: &QSConfig,
+    runs: &[Run],
+    bounds: &[(usize, usize)],
+    output: &Path,
+    offset: usize,
+    buffer: usize,
+) -> io::Result<PassTimes> {
+    let record_size = qs_config.record_size;
+    let descending = qs_config.sort_order == 1;
+    let mut times = PassTimes::default();
+    let part_start = Instant::now();
+
+    let start = Instant::now();
+    let mut readers = Vec::with_capacity(runs.len());
+    for (run, &(first, last)) in runs.iter().zip(bounds.iter()) {
+        if first < last {
+            readers.push(RunReader::open(
+                &run.path,
+                first,
+                last,
+                record_size,
+                buffer,
+            )?);
+        }
+    }
+    times.read += start.elapsed();
+
+    let start = Instant::now();
+    let mut out = OpenOptions::new().write(true).open(output)?;
+    out.seek(SeekFrom::Start((offset * record_size) as u64))?;
+    times.write += start.elapsed();
+
+    let out_bytes = std::cmp::max(1, buffer / record_size) * record_size;
+    let mut out_buf = Vec::with_capacity(out_bytes);
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = readers
+        .iter()
+        .enumerate()
+        .map(|(j, reader)| Reverse((order_key(reader.current(record_size), descending), j)))
+        .collect();
+
+    while let Some(Reverse((_, j))) = heap.pop() {
+        let reader = &mut readers[j];
+        out_buf.extend_from_slice(reader.current(record_size));
+        reader.pos += record_size;
+
+        if out_buf.len() == out_bytes {
+            let start = Instant::now();
+            out.write_all(&out_buf)?;
+            out_buf.clear();
+            times.write += start.elapsed();
+        }
+
+        if reader.pos == reader.end {
+            if reader.remaining == 0 {
+                continue;
+            }
+            let start = Instant::now();
+            reader.refill(record_size)?;
+            times.read += start.elapsed();
+        }
+        heap.push(Reverse((
+            order_key(reader.current(record_size), descending),
+            j,
+        )));
+    }
+
+    let start = Instant::now();
+    out.write_all(&out_buf)?;
+    times.write += start.elapsed();
+
+    times.compute = part_start.elapsed() - times.read - times.write;
+    Ok(times)
+}
+
+// Merges all the runs into `output` in one pass, a part per thread
+fn merge_pass(qs_config: &QSConfig, runs: &[Run], output: &Path) -> io::Result<PassTimes> {
+    let record_size = qs_config.record_size;
+    let descending = qs_config.sort_order == 1;
+    let parts = qs_config.num_threads as usize;
+    let total: usize = runs.iter().map(|run| run.len).sum();
+
+    let start = Instant::now();
+    File::create(output)?.set_len((total * record_size) as u64)?;
+    let mut times = PassTimes {
+        write: start.elapsed(),
+        ..PassTimes::default()
+    };
+    if runs.is_empty() {
+        return Ok(times);
+    }
+
+    // Split points of every run at every splitter, cuts[q][j] for run j
+    let start = Instant::now();
+    let splitters = choose_splitters(runs, parts);
+    let cuts: Vec<Vec<usize>> = splitters
+        .par_iter()
+        .map(|&splitter| {
+            runs.iter()
+                .map(|run| {
+                    let mut file = File::open(&run.path)?;
+                    lower_bound(&mut file, run, record_size, splitter, descending)
+                })
+                .collect()
+        })
+        .collect::<io::Result<_>>()?;
+    times.read += start.elapsed();
+
+    // Records of every run in every part, and first record of every part
+    let bounds: Vec<Vec<(usize, usize)>> = (0..parts)
+        .map(|q| {
+            runs.iter()
+                .enumerate()
+                .map(|(j, run)| {
+                    let first = if q == 0 { 0 } else { cuts[q - 1][j] };
+                    let last = if q == parts - 1 { run.len } else { cuts[q][j] };
+                    (first, last)
+                })
+                .collect()
+        })
+        .collect();
+    let offsets: Vec<usize> = bounds
+        .iter()
+        .scan(0, |offset, part| {
+            let first = *offset;
+            *offset += part
+                .iter()
+                .map(|&(first, last)| last - first)
+                .sum::<usize>();
+            Some(first)
+        })
+        .collect();
+
+    let memory = (qs_config.memory_mb as usize) << 20;
+    let buffer = std::cmp::max(MIN_BUFFER, memory / (parts * (runs.len() + 1)));
+    let part_times = bounds
+        .par_iter()
+        .zip(offsets.par_iter())
+        .map(|(part, &offset)| merge_part(qs_config, runs, part, output, offset, buffer))
+        .collect::<io::Result<Vec<PassTimes>>>()?;
+
+    Ok(part_times.into_iter().fold(times, PassTimes::add))
+}
+
+// Merges the runs into `output`, first merging groups of them into longer
+// runs while there are too many to keep open. The longer runs are added to
+// `runs`, so that they are removed with the others. Returns the times and the
+// number of passes.
+fn merge_runs(
+    qs_config: &QSConfig,
+    runs: &mut Vec<Run>,
+    output: &str,
+) -> io::Result<(PassTimes, usize)> {
+    let fan_in = std::cmp::max(2, MAX_OPEN_FILES / qs_config.num_threads as usize);
+    let mut times = PassTimes::default();
+    let mut level = 0..runs.len();
+    let mut passes = 1;
+
+    while level.len() > fan_in {
+        let next = runs.len();
+        for first in level.clone().step_by(fan_in) {
+            let group = first..std::cmp::min(first + fan_in, level.end);
+            runs.push(Run {
+                path: temp_path(qs_config, &format!("pass{}-run{}", passes, runs.len())),
+                len: runs[group.clone()].iter().map(|run| run.len).sum(),
+                sample: merged_sample(&runs[group.clone()]),
+            });
+            let merged = runs.last().unwrap();
+            times = times.add(merge_pass(qs_config, &runs[group], &merged.path)?);
+        }
+
+        // The merged runs are no longer needed, free their disk space early
+        for run in runs[level].iter() {
+            fs::remove_file(&run.path).ok();
+        }
+        level = next..runs.len();
+        passes += 1;
+    }
+
+    times = times.add(merge_pass(qs_config, &runs[level], Path::new(output))?);
+    Ok((times, passes))
+}
+
+fn external_sort(qs_config: &QSConfig, input: &str, output: &str) -> io::Result<SortTimes> {
+    let mut runs = Vec::new();
+    let mut runs_times = PassTimes::default();
+    let start = Instant::now();
+    let formed = form_runs(qs_config, input, &mut runs, &mut runs_times);
+    let runs_wall = start.elapsed();
+    let num_runs = runs.len();
+
+    let start = Instant::now();
+    let merged = formed.and_then(|_| merge_runs(qs_config, &mut runs, output));
+    let merge_wall = start.elapsed();
+
+    // The temporary files go whether the sort worked or not, and a file that
+    // cannot be removed does not hide the result of the sort
+    for run in runs.iter() {
+        fs::remove_file(&run.path).ok();
+    }
+    let (merge, merge_passes) = merged?;
+    Ok(SortTimes {
+        runs: runs_times,
+        runs_wall,
+        merge,
+        merge_wall,
+        num_runs,
+        merge_passes,
+    })
+}
+
+/*************************************
+ * Input files
+ *************************************/
+// --generate writes -n records of the --dist keys to the input file, the rest
+// of every record being its index repeated, before sorting it. The keys are
+// generated in memory, so files larger than memory have to come from
+// elsewhere.
+
+fn generate_file(qs_config: &QSConfig, path: &str) -> io::Result<()> {
+    let record_size = qs_config.record_size;
+    let keys = dist::generate(
+        qs_config.dist,
+        qs_config.num_elements as usize,
+        64,
+        qs_config.seed,
+        qs_config.swaps,
+    );
+
+    let mut file = File::create(path)?;
+    let mut buf = vec![0; chunk_records(qs_config) * record_size];
+    for (c, part) in keys.chunks(buf.len() / record_size).enumerate() {
+        let first = c * (buf.len() / record_size);
+        let bytes = part.len() * record_size;
+        buf[..bytes]
+            .par_chunks_mut(record_size)
+            .zip(part.par_iter())
+            .enumerate()
+            .for_each(|(i, (record, key))| {
+                record[..KEY_BYTES].copy_from_slice(&key.to_le_bytes());
+                let index = ((first + i) as u64).to_le_bytes();
+                for (b, byte) in record[KEY_BYTES..].iter_mut().enumerate() {
+                    *byte = index[b % KEY_BYTES];
+                }
+            });
+        file.write_all(&buf[..bytes])?;
+    }
+    Ok(())
+}
+
+// Multiset hash of the records of a file, and whether they are in order
+fn scan_file(qs_config: &QSConfig, path: &str) -> io::Result<((u64, u64), bool)> {
+    let record_size = qs_config.record_size;
+    let descending = qs_config.sort_order == 1;
+    let mut file = File::open(path)?;
+    let mut buf = vec![0; chunk_records(qs_config) * record_size];
+    let mut hash = (0, 0);
+    let mut sorted = true;
+    let mut last = 0;
+
+    loop {
+        let bytes = read_full(&mut file, &mut buf)?;
+        if bytes == 0 {
+            break;
+        }
+        let chunk = &buf[..bytes];
+        hash = verify::combine(
+            hash,
+            verify::hash_fingerprints(chunk.par_chunks(record_size).map(record_fingerprint)),
+        );
+        let first = order_key(chunk, descending);
+        sorted = sorted
+            && last <= first
+            && (1..bytes / record_size).into_par_iter().all(|i| {
+                order_key(&chunk[(i - 1) * record_size..], descending)
+                    <= order_key(&chunk[i * record_size..], descending)
+            });
+        last = order_key(&chunk[bytes - record_size..], descending);
+    }
+    Ok((hash, sorted))
+}
+
+pub fn do_external_runs(qs_config: &QSConfig) {
+    let input = qs_config.external.as_ref().unwrap();
+    let output = match &qs_config.output {
+        Some(output) => output.clone(),
+        None => format!("{}.sorted", input),
+    };
+
+    if qs_config.generate {
+        generate_file(qs_config, input)
+            .unwrap_or_else(|err| panic!("Could not write {}: {}", input, err));
+    }
+    let file_len = fs::metadata(input)
+        .unwrap_or_else(|err| panic!("Could not read {}: {}", input, err))
+        .len();
+    println!(
+        "{} records of {} bytes, {} MB of memory, chunks of {} records\n",
+        file_len / qs_config.record_size as u64,
+        qs_config.record_size,
+        qs_config.memory_mb,
+        chunk_records(qs_config)
+    );
+
+    let (_, times) = best_run(qs_config, || {
+        let times = external_sort(qs_config, input, &output)
+            .unwrap_or_else(|err| panic!("Could not sort {} into {}: {}", input, output, err));
+        (times.runs_wall + times.merge_wall, times)
+    });
+
+    println!(
+        "[sort-external runs]: \t\t[{:?}] ms",
+        as_ms(times.runs_wall)
+    );
+    println!(
+        "++++ \t\t(read {:?} ms, sort {:?} ms, write {:?} ms, {} runs)\n",
+        as_ms(times.runs.read),
+        as_ms(times.runs.compute),
+        as_ms(times.runs.write),
+        times.num_runs
+    );
+    println!(
+        "[sort-external merge]: \t\t[{:?}] ms",
+        as_ms(times.merge_wall)
+    );
+    println!(
+        "++++ \t\t(read {:?} ms, merge {:?} ms, write {:?} ms, summed over {} threads, {} passes)\n",
+        as_ms(times.merge.read),
+        as_ms(times.merge.compute),
+        as_ms(times.merge.write),
+        qs_config.num_threads,
+        times.merge_passes
+    );
+
+    if qs_config.verify {
+        let (input_hash, _) = scan_file(qs_config, input)
+            .unwrap_or_else(|err| panic!("Could not read {}: {}", input, err));
+        let (output_hash, sorted) = scan_file(qs_config, &output)
+            .unwrap_or_else(|err| panic!("Could not read {}: {}", output, err));
+        assert!(sorted, "external: output not sorted");
+        assert!(
+            output_hash == input_hash,
+            "external: output not a permutation of the input"
+        );
+        println!("All outputs verified");
+    }
+}
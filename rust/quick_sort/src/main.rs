@@ -4,11 +4,22 @@ extern crate num_cpus;
 extern crate rayon;
 extern crate rand;
 
+mod bitonic;
+mod dist;
+mod element;
+mod external;
+mod mergesort;
+mod quicksort;
+mod samplesort;
+mod select;
+mod verify;
+
 use clap::{App, Arg};
 use rayon::prelude::*;
 use std::time::{Duration, Instant};
 use rand::{Rng, thread_rng};
-use rand::distributions::{Uniform};
+use dist::Dist;
+use element::{Record, SortElement, Tagged, TotalF64};
 
 fn main() {
     let qs_config = parse_arguments();
@@ -19,17 +30,74 @@ fn main() {
         .build_global()
         .unwrap();
 
-    do_runs(&qs_config);
+    if qs_config.external.is_some() {
+        external::do_external_runs(&qs_config);
+        return;
+    }
+
+    match qs_config.elem_type {
+        ElemType::U32 => run::<u32>(&qs_config),
+        ElemType::U64 => run::<u64>(&qs_config),
+        ElemType::F64 => run::<TotalF64>(&qs_config),
+        ElemType::Record => run::<Record>(&qs_config),
+        ElemType::String => run::<String>(&qs_config),
+    }
+}
+
+fn run<T: SortElement>(qs_config: &QSConfig) {
+    let v_orig: Vec<T> = dist::generate(
+        qs_config.dist,
+        qs_config.num_elements as usize,
+        T::KEY_BITS,
+        qs_config.seed,
+        qs_config.swaps,
+    )
+    .into_par_iter()
+    .enumerate()
+    .map(|(index, key)| T::from_key(key, index))
+    .collect();
+    println!("Elements of {} bytes\n", std::mem::size_of::<T>());
+
+    if qs_config.select {
+        select::do_select_runs(qs_config, &v_orig[..]);
+    } else {
+        do_runs(qs_config, &v_orig[..]);
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum ElemType {
+    U32,
+    U64,
+    F64,
+    Record,
+    String,
 }
 
 // Configuration file, reflects command line options
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct QSConfig {
     pub num_elements: u32,
     pub sort_order: u32,
     pub num_of_runs: u32,
     pub num_threads: u32,
     pub code_config: u32,
+    pub cutoff: u32,
+    pub par_partition: bool,
+    pub dist: Dist,
+    pub seed: u64,
+    pub swaps: u64,
+    pub elem_type: ElemType,
+    pub verify: bool,
+    pub external: Option<String>,
+    pub output: Option<String>,
+    pub record_size: usize,
+    pub memory_mb: u32,
+    pub tmp_dir: Option<String>,
+    pub generate: bool,
+    pub select: bool,
+    pub nth: u32,
+    pub top_k: u32,
 }
 
 pub fn parse_arguments() -> QSConfig {
@@ -73,6 +141,101 @@ pub fn parse_arguments() -> QSConfig {
                 .value_name("CODE")
                 .help("Enter 0 for all code, 1 for parallel only, 2 for serial only (default: 0)"),
         )
+        .arg(
+            Arg::with_name("CUTOFF")
+                .long("cutoff")
+                .value_name("CUTOFF")
+                .help("length under which the parallel quicksort sorts serially (default: 10000)"),
+        )
+        .arg(
+            Arg::with_name("PAR_PARTITION")
+                .long("par_partition")
+                .help("partition long slices in parallel in the parallel quicksort"),
+        )
+        .arg(
+            Arg::with_name("ELEM_TYPE")
+                .long("type")
+                .value_name("ELEM_TYPE")
+                .possible_values(&["u32", "u64", "f64", "record", "string"])
+                .help("type of the elements to sort, records being a u64 key and 56 bytes of payload (default: u64)"),
+        )
+        .arg(
+            Arg::with_name("DIST")
+                .long("dist")
+                .value_name("DIST")
+                .possible_values(&dist::DIST_NAMES)
+                .help("distribution of the elements to sort (default: uniform)"),
+        )
+        .arg(
+            Arg::with_name("SEED")
+                .long("seed")
+                .value_name("SEED")
+                .help("seed of the input generator (default: random)"),
+        )
+        .arg(
+            Arg::with_name("SWAPS")
+                .long("swaps")
+                .value_name("SWAPS")
+                .help("random swaps of the nearly-sorted distribution (default: 1% of the elements)"),
+        )
+        .arg(
+            Arg::with_name("VERIFY")
+                .long("verify")
+                .help("check that every output is a sorted permutation of the input, and that the stable sorts are stable"),
+        )
+        .arg(
+            Arg::with_name("EXTERNAL")
+                .long("external")
+                .value_name("FILE")
+                .help("sort the binary file FILE of fixed-width records out of core instead of running the in-memory sorts"),
+        )
+        .arg(
+            Arg::with_name("OUTPUT")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("file the external sort writes to (default: the input file with .sorted appended)"),
+        )
+        .arg(
+            Arg::with_name("RECORD_SIZE")
+                .long("record_size")
+                .value_name("BYTES")
+                .help("bytes of every record of the external sort, starting with a little-endian u64 key (default: 8)"),
+        )
+        .arg(
+            Arg::with_name("MEMORY")
+                .long("memory")
+                .value_name("MB")
+                .help("memory budget of the external sort in MB (default: 256)"),
+        )
+        .arg(
+            Arg::with_name("TMP_DIR")
+                .long("tmp_dir")
+                .value_name("DIR")
+                .help("directory of the runs of the external sort (default: the system temporary directory)"),
+        )
+        .arg(
+            Arg::with_name("GENERATE")
+                .long("generate")
+                .help("write NUM_ELEMENTS records of the distribution to the external file before sorting it"),
+        )
+        .arg(
+            Arg::with_name("SELECT")
+                .long("select")
+                .help("time selection, top-k and stable partition against sorting instead of the sorts"),
+        )
+        .arg(
+            Arg::with_name("NTH")
+                .long("nth")
+                .value_name("NTH")
+                .help("rank of the element to select, and of the pivot of the partition (default: NUM_ELEMENTS / 2)"),
+        )
+        .arg(
+            Arg::with_name("TOP_K")
+                .long("top_k")
+                .value_name("TOP_K")
+                .help("number of first elements to find (default: 1000)"),
+        )
         .get_matches();
 
     // Find number of cpus available
@@ -85,6 +248,28 @@ pub fn parse_arguments() -> QSConfig {
     let num_threads =
         value_t!(matches.value_of("NUMBER_OF_THREADS"), u32).unwrap_or(max_threads as u32);
     let code_config = value_t!(matches.value_of("CODE"), u32).unwrap_or(0);
+    let cutoff = value_t!(matches.value_of("CUTOFF"), u32).unwrap_or(10000);
+    let par_partition = matches.is_present("PAR_PARTITION");
+    let elem_type = match matches.value_of("ELEM_TYPE").unwrap_or("u64") {
+        "u32" => ElemType::U32,
+        "f64" => ElemType::F64,
+        "record" => ElemType::Record,
+        "string" => ElemType::String,
+        _ => ElemType::U64,
+    };
+    let dist = Dist::from_name(matches.value_of("DIST").unwrap_or("uniform"));
+    let seed = value_t!(matches.value_of("SEED"), u64).unwrap_or_else(|_| thread_rng().gen());
+    let swaps = value_t!(matches.value_of("SWAPS"), u64).unwrap_or(num_elements as u64 / 100);
+    let verify = matches.is_present("VERIFY");
+    let external = matches.value_of("EXTERNAL").map(String::from);
+    let output = matches.value_of("OUTPUT").map(String::from);
+    let record_size = value_t!(matches.value_of("RECORD_SIZE"), usize).unwrap_or(8);
+    let memory_mb = value_t!(matches.value_of("MEMORY"), u32).unwrap_or(256);
+    let tmp_dir = matches.value_of("TMP_DIR").map(String::from);
+    let generate = matches.is_present("GENERATE");
+    let select = matches.is_present("SELECT");
+    let nth = value_t!(matches.value_of("NTH"), u32).unwrap_or(num_elements / 2);
+    let top_k = value_t!(matches.value_of("TOP_K"), u32).unwrap_or(1000);
 
     // Check if values are correct for the mandelbrot program
     assert!(num_elements > 0);
@@ -92,133 +277,233 @@ pub fn parse_arguments() -> QSConfig {
     assert!(num_threads > 0);
     assert!(num_of_runs > 0);
     assert!(code_config < 3);
+    assert!(cutoff > 0);
+    assert!(record_size >= 8);
+    assert!(memory_mb > 0);
+    assert!(nth < num_elements);
 
     //
-    println!("Configuration: \nnum_elements: {}, sort_order: {}, num_threads: {}, num_of_runs: {}, code_config: {}\n",
-        num_elements, sort_order, num_threads, num_of_runs, code_config);
+    println!("Configuration: \nnum_elements: {}, sort_order: {}, num_threads: {}, num_of_runs: {}, code_config: {}, cutoff: {}, par_partition: {}, elem_type: {:?}, dist: {:?}, seed: {}, verify: {}\n",
+        num_elements, sort_order, num_threads, num_of_runs, code_config, cutoff, par_partition, elem_type, dist, seed, verify);
 
     // Return the struct that can be used by the functions
     QSConfig {
-        num_elements: num_elements,
-        sort_order: sort_order,
-        num_threads: num_threads,
-        num_of_runs: num_of_runs,
-        code_config: code_config,
+        num_elements,
+        sort_order,
+        num_threads,
+        num_of_runs,
+        code_config,
+        cutoff,
+        par_partition,
+        dist,
+        seed,
+        swaps,
+        elem_type,
+        verify,
+        external,
+        output,
+        record_size,
+        memory_mb,
+        tmp_dir,
+        generate,
+        select,
+        nth,
+        top_k,
+    }
+}
+
+pub fn as_ms(duration: Duration) -> f64 {
+    duration.as_micros() as f64 / 1000_f64
+}
+
+// Minimum time over the runs of `run`, which times itself, and the result of
+// the fastest run
+pub fn best_run<R, O>(qs_config: &QSConfig, mut run: O) -> (Duration, R)
+where
+    O: FnMut() -> (Duration, R),
+{
+    let mut best: Option<(Duration, R)> = None;
+    for _ in 0..qs_config.num_of_runs {
+        let (time, r) = run();
+        match &best {
+            Some((best_time, _)) if *best_time <= time => {}
+            _ => best = Some((time, r)),
+        }
     }
+    best.unwrap()
+}
+
+// Minimum time of `op` over the runs, on a fresh copy of the input each time,
+// and the result of the fastest run
+pub fn time_runs<T: Clone, R, O>(qs_config: &QSConfig, v_orig: &[T], mut op: O) -> (Duration, R)
+where
+    O: FnMut(&mut Vec<T>) -> R,
+{
+    best_run(qs_config, || {
+        let mut v = v_orig.to_vec();
+        let start = Instant::now();
+        let r = op(&mut v);
+        (start.elapsed(), r)
+    })
 }
 
-pub fn do_runs(qs_config: &QSConfig) {
-    let num_runs = qs_config.num_of_runs;
-    assert!(num_runs > 0);
+pub fn do_runs<T: SortElement>(qs_config: &QSConfig, v_orig: &[T]) {
+    assert!(qs_config.num_of_runs > 0);
 
     let mut stable_serial_time = Duration::new(10000, 0);
     let mut unstable_serial_time = Duration::new(10000, 0);
-    let mut stable_par_time = Duration::new(10000, 0);
-    let mut unstable_par_time = Duration::new(10000, 0);
+    let mut quick_serial_time = Duration::new(10000, 0);
+    let mut bitonic_serial_time = Duration::new(10000, 0);
 
-    let range = Uniform::new(std::u64::MIN, std::u64::MAX);
+    // Hash of the input, for the permutation check
+    let input_hash = if qs_config.verify {
+        verify::multiset_hash(v_orig)
+    } else {
+        (0, 0)
+    };
+
+    // Times `sort` over the runs and checks the output of the fastest one
+    let time_sort = |name: &str, sort: fn(&QSConfig, &mut [T])| {
+        let (time, v) = time_runs(qs_config, v_orig, |v| {
+            sort(qs_config, &mut v[..]);
+            std::mem::take(v)
+        });
+        verify::check(qs_config, name, input_hash, &v[..]);
+        time
+    };
 
-    let v_orig: Vec<u64> = thread_rng().sample_iter(&range).take(qs_config.num_elements as usize).collect();
     if (qs_config.code_config == 0) || (qs_config.code_config == 2) {
         // Stable serial sort
-        for _ in 0..num_runs {
-            let mut v = v_orig.clone();
-            let serial_start = Instant::now();
-            stable_sort_serial(&qs_config, &mut v[..]);
-            let serial_end = Instant::now();
-
-            stable_serial_time =
-                std::cmp::min(stable_serial_time, serial_end.duration_since(serial_start));
-            // assert!(is_sorted(&mut v[..], qs_config.sort_order));
-        }
-
-        // Check correctness
-
+        stable_serial_time = time_sort("stable serial", stable_sort_serial);
         println!(
             "[sort-stable-rust serial]: \t[{:?}] ms",
-            stable_serial_time.as_micros() as f64 / 1000 as f64
+            as_ms(stable_serial_time)
+        );
+        verify::check_stable(
+            qs_config,
+            "stable serial",
+            v_orig,
+            stable_sort_serial::<Tagged<T>>,
         );
 
         // Unstable serial sort
-        for _ in 0..num_runs {
-            let mut v = v_orig.clone();
-            let serial_start = Instant::now();
-            unstable_sort_serial(&qs_config, &mut v[..]);
-            let serial_end = Instant::now();
-
-            unstable_serial_time = std::cmp::min(
-                unstable_serial_time,
-                serial_end.duration_since(serial_start),
-            );
-        }
+        unstable_serial_time = time_sort("unstable serial", unstable_sort_serial);
+        println!(
+            "[sort-unstable-rust serial]: \t[{:?}] ms",
+            as_ms(unstable_serial_time)
+        );
 
-        // Check correctness
-        // assert!(is_sorted(&mut v[..], qs_config.sort_order));
+        // Hand-written serial quicksort
+        quick_serial_time = time_sort("quick serial", quick_sort_serial);
+        println!(
+            "[sort-quick-rust serial]: \t[{:?}] ms",
+            as_ms(quick_serial_time)
+        );
 
+        // Bitonic sorting network
+        bitonic_serial_time = time_sort("bitonic serial", bitonic_sort_serial);
         println!(
-            "[sort-unstable-rust serial]: \t[{:?}] ms",
-            unstable_serial_time.as_micros() as f64 / 1000 as f64
+            "[sort-bitonic-rust serial]: \t[{:?}] ms",
+            as_ms(bitonic_serial_time)
         );
     }
 
     if (qs_config.code_config == 0) || (qs_config.code_config == 1) {
         // Stable parallel sort
-        for _ in 0..num_runs {
-            let mut v = v_orig.clone();
-            let stable_par_start = Instant::now();
-            stable_sort_par(&qs_config, &mut v[..]);
-            let stable_par_end = Instant::now();
-
-            stable_par_time = std::cmp::min(
-                stable_par_time,
-                stable_par_end.duration_since(stable_par_start),
+        let stable_par_time = time_sort("stable par", stable_sort_par);
+        println!("[sort-stable par]: \t\t[{:?}] ms", as_ms(stable_par_time));
+        verify::check_stable(
+            qs_config,
+            "stable par",
+            v_orig,
+            stable_sort_par::<Tagged<T>>,
+        );
+        if qs_config.code_config == 0 {
+            println!(
+                "++++ \t\t({:.2}x speedup from {:?} threads)\n",
+                stable_serial_time.as_micros() as f64 / stable_par_time.as_micros() as f64,
+                qs_config.num_threads
             );
         }
 
+        // Unstable parallel sort
+        let unstable_par_time = time_sort("unstable par", unstable_sort_par);
         println!(
-            "[sort-stable par]: \t\t[{:?}] ms",
-            stable_par_time.as_micros() as f64 / 1000 as f64
+            "[sort-unstable par]: \t\t[{:?}] ms",
+            as_ms(unstable_par_time)
         );
         if qs_config.code_config == 0 {
             println!(
                 "++++ \t\t({:.2}x speedup from {:?} threads)\n",
-                stable_serial_time.as_micros() as f64 / stable_par_time.as_micros() as f64,
+                unstable_serial_time.as_micros() as f64 / unstable_par_time.as_micros() as f64,
                 qs_config.num_threads
             );
         }
 
-        // Unstable parallel sort
-        for _ in 0..num_runs {
-            let mut v = v_orig.clone();
-            let stable_par_start = Instant::now();
-            stable_sort_par(&qs_config, &mut v[..]);
-            let stable_par_end = Instant::now();
-
-            unstable_par_time = std::cmp::min(
-                unstable_par_time,
-                stable_par_end.duration_since(stable_par_start),
+        // Hand-written parallel quicksort
+        let quick_par_time = time_sort("quick par", quick_sort_par);
+        println!("[sort-quick par]: \t\t[{:?}] ms", as_ms(quick_par_time));
+        if qs_config.code_config == 0 {
+            println!(
+                "++++ \t\t({:.2}x speedup from {:?} threads)\n",
+                quick_serial_time.as_micros() as f64 / quick_par_time.as_micros() as f64,
+                qs_config.num_threads
             );
         }
 
+        // Samplesort, unstable
+        let sample_par_time = time_sort("sample par", sample_sort_par);
+        println!("[sort-sample par]: \t\t[{:?}] ms", as_ms(sample_par_time));
+        if qs_config.code_config == 0 {
+            println!(
+                "++++ \t\t({:.2}x speedup from {:?} threads)\n",
+                unstable_serial_time.as_micros() as f64 / sample_par_time.as_micros() as f64,
+                qs_config.num_threads
+            );
+        }
+
+        // Multiway mergesort, stable
+        let multiway_par_time = time_sort("multiway par", multiway_sort_par);
         println!(
-            "[sort-unstable par]: \t\t[{:?}] ms",
-            unstable_par_time.as_micros() as f64 / 1000 as f64
+            "[sort-multiway par]: \t\t[{:?}] ms",
+            as_ms(multiway_par_time)
+        );
+        verify::check_stable(
+            qs_config,
+            "multiway par",
+            v_orig,
+            multiway_sort_par::<Tagged<T>>,
         );
         if qs_config.code_config == 0 {
             println!(
                 "++++ \t\t({:.2}x speedup from {:?} threads)\n",
-                unstable_serial_time.as_micros() as f64 / unstable_par_time.as_micros() as f64,
+                stable_serial_time.as_micros() as f64 / multiway_par_time.as_micros() as f64,
+                qs_config.num_threads
+            );
+        }
+
+        // Bitonic sorting network, statically scheduled
+        let bitonic_par_time = time_sort("bitonic par", bitonic_sort_par);
+        println!("[sort-bitonic par]: \t\t[{:?}] ms", as_ms(bitonic_par_time));
+        if qs_config.code_config == 0 {
+            println!(
+                "++++ \t\t({:.2}x speedup from {:?} threads)\n",
+                bitonic_serial_time.as_micros() as f64 / bitonic_par_time.as_micros() as f64,
                 qs_config.num_threads
             );
         }
     }
+
+    if qs_config.verify {
+        println!("All outputs verified");
+    }
 }
 
 /*************************************
  * Sort functions
  *************************************/
 // The serial version of the sorting
-pub fn stable_sort_serial(qs_config: &QSConfig, num_vec: &mut [u64]) {
+pub fn stable_sort_serial<T: SortElement>(qs_config: &QSConfig, num_vec: &mut [T]) {
     if qs_config.sort_order == 0 {
         num_vec.sort();
     } else {
@@ -226,7 +511,7 @@ pub fn stable_sort_serial(qs_config: &QSConfig, num_vec: &mut [u64]) {
     }
 }
 
-pub fn unstable_sort_serial(qs_config: &QSConfig, num_vec: &mut [u64]) {
+pub fn unstable_sort_serial<T: SortElement>(qs_config: &QSConfig, num_vec: &mut [T]) {
     if qs_config.sort_order == 0 {
         num_vec.sort_unstable();
     } else {
@@ -234,8 +519,8 @@ pub fn unstable_sort_serial(qs_config: &QSConfig, num_vec: &mut [u64]) {
     }
 }
 
-// The serial version of the mandelbrot set calculation.
-pub fn stable_sort_par(qs_config: &QSConfig, num_vec: &mut [u64]) {
+// The parallel version of the sorting
+pub fn stable_sort_par<T: SortElement>(qs_config: &QSConfig, num_vec: &mut [T]) {
     if qs_config.sort_order == 0 {
         num_vec.par_sort();
     } else {
@@ -243,8 +528,7 @@ pub fn stable_sort_par(qs_config: &QSConfig, num_vec: &mut [u64]) {
     }
 }
 
-// The serial version of the mandelbrot set calculation.
-pub fn unstable_sort_par(qs_config: &QSConfig, num_vec: &mut [u32]) {
+pub fn unstable_sort_par<T: SortElement>(qs_config: &QSConfig, num_vec: &mut [T]) {
     if qs_config.sort_order == 0 {
         num_vec.par_sort_unstable();
     } else {
@@ -252,6 +536,51 @@ pub fn unstable_sort_par(qs_config: &QSConfig, num_vec: &mut [u32]) {
     }
 }
 
+// Our own quicksort, serial and with rayon::join
+pub fn quick_sort_serial<T: SortElement>(qs_config: &QSConfig, num_vec: &mut [T]) {
+    if qs_config.sort_order == 0 {
+        quicksort::quicksort_serial(num_vec, |a, b| a < b);
+    } else {
+        quicksort::quicksort_serial(num_vec, |a, b| b < a);
+    }
+}
+
+pub fn quick_sort_par<T: SortElement>(qs_config: &QSConfig, num_vec: &mut [T]) {
+    let cutoff = qs_config.cutoff as usize;
+    if qs_config.sort_order == 0 {
+        quicksort::quicksort_par(num_vec, |a, b| a < b, cutoff, qs_config.par_partition);
+    } else {
+        quicksort::quicksort_par(num_vec, |a, b| b < a, cutoff, qs_config.par_partition);
+    }
+}
+
+// The algorithms of GNU parallel mode, against the sorts of the standard
+// library they stand in for
+pub fn sample_sort_par<T: SortElement>(qs_config: &QSConfig, num_vec: &mut [T]) {
+    if qs_config.sort_order == 0 {
+        samplesort::samplesort_par(num_vec, |a, b| a < b);
+    } else {
+        samplesort::samplesort_par(num_vec, |a, b| b < a);
+    }
+}
+
+pub fn multiway_sort_par<T: SortElement>(qs_config: &QSConfig, num_vec: &mut [T]) {
+    if qs_config.sort_order == 0 {
+        mergesort::multiway_mergesort_par(num_vec, |a, b| a < b);
+    } else {
+        mergesort::multiway_mergesort_par(num_vec, |a, b| b < a);
+    }
+}
+
+// The bitonic network, the same compare-exchanges whatever the input
+pub fn bitonic_sort_serial<T: SortElement>(qs_config: &QSConfig, num_vec: &mut [T]) {
+    bitonic::bitonic_sort_serial(num_vec, qs_config.sort_order == 1);
+}
+
+pub fn bitonic_sort_par<T: SortElement>(qs_config: &QSConfig, num_vec: &mut [T]) {
+    bitonic::bitonic_sort_par(num_vec, qs_config.sort_order == 1);
+}
+
 // Sort Checker
 pub fn is_sorted<T: Send + Ord>(v: &[T], sort_order: u32) -> bool {
     if sort_order == 0 {
@@ -0,0 +1,174 @@
+use crate::samplesort::compare;
+use rayon::prelude::*;
+
+/*************************************
+ * Parallel multiway mergesort
+ *************************************/
+// The input is cut into one run per thread and the runs are sorted in
+// parallel, then merged all at once into a scratch buffer. The output is cut
+// into as many parts of the same length, and the part starting at rank r
+// takes from every run the elements of rank below r in the merged order:
+// co-ranking finds these counts by binary search, so the parts merge in
+// parallel without looking at each other. Ties are broken by run, then by
+// position, which keeps the sort stable.
+
+// Inputs shorter than this are sorted serially
+const MERGESORT_MIN: usize = 1 << 12;
+
+// Rank in the merged order of element i of run j
+fn rank<T, F>(runs: &[&[T]], j: usize, i: usize, is_less: &F) -> usize
+where
+    F: Fn(&T, &T) -> bool,
+{
+    let x = &runs[j][i];
+    runs.iter()
+        .enumerate()
+        .map(|(other, run)| {
+            if other < j {
+                run.partition_point(|y| !is_less(x, y))
+            } else if other > j {
+                run.partition_point(|y| is_less(y, x))
+            } else {
+                i
+            }
+        })
+        .sum()
+}
+
+// Number of elements of every run with a rank below `r`
+fn co_rank<T, F>(runs: &[&[T]], r: usize, is_less: &F) -> Vec<usize>
+where
+    F: Fn(&T, &T) -> bool,
+{
+    (0..runs.len())
+        .map(|j| {
+            let (mut lo, mut hi) = (0, runs[j].len());
+            while lo < hi {
+                let mid = (lo + hi) / 2;
+                if rank(runs, j, mid, is_less) < r {
+                    lo = mid + 1;
+                } else {
+                    hi = mid;
+                }
+            }
+            lo
+        })
+        .collect()
+}
+
+// Whether the head of piece a comes before the head of piece b in the merged
+// order, an exhausted piece coming last and the first piece winning ties
+fn beats<T, F>(pieces: &[&mut [T]], heads: &[usize], a: usize, b: usize, is_less: &F) -> bool
+where
+    F: Fn(&T, &T) -> bool,
+{
+    let head = |j: usize| pieces.get(j).and_then(|piece| piece.get(heads[j]));
+    match (head(a), head(b)) {
+        (None, _) => false,
+        (Some(_), None) => true,
+        (Some(x), Some(y)) => is_less(x, y) || (a < b && !is_less(y, x)),
+    }
+}
+
+// Plays the matches of the subtree under `node` of the loser tree, leaving the
+// loser of every match in its node and returning the winner
+fn play<T, F>(
+    tree: &mut [usize],
+    pieces: &[&mut [T]],
+    heads: &[usize],
+    node: usize,
+    is_less: &F,
+) -> usize
+where
+    F: Fn(&T, &T) -> bool,
+{
+    if node >= tree.len() {
+        return node - tree.len();
+    }
+
+    let left = play(tree, pieces, heads, 2 * node, is_less);
+    let right = play(tree, pieces, heads, 2 * node + 1, is_less);
+    if beats(pieces, heads, left, right, is_less) {
+        tree[node] = right;
+        left
+    } else {
+        tree[node] = left;
+        right
+    }
+}
+
+// Moves the sorted pieces into dest in order, the first piece winning ties.
+// A loser tree over the heads of the pieces finds the next element with one
+// match per level, log p comparisons instead of p.
+fn merge_into<T, F>(mut pieces: Vec<&mut [T]>, dest: &mut [T], is_less: &F)
+where
+    F: Fn(&T, &T) -> bool,
+{
+    let mut heads = vec![0; pieces.len()];
+    let leaves = pieces.len().next_power_of_two();
+    let mut tree = vec![0; leaves];
+    tree[0] = play(&mut tree[..], &pieces[..], &heads[..], 1, is_less);
+
+    for slot in dest.iter_mut() {
+        let mut winner = tree[0];
+        std::mem::swap(slot, &mut pieces[winner][heads[winner]]);
+        heads[winner] += 1;
+
+        // Only the matches on the path of the winner change
+        let mut node = (winner + leaves) / 2;
+        while node > 0 {
+            if beats(&pieces[..], &heads[..], tree[node], winner, is_less) {
+                std::mem::swap(&mut tree[node], &mut winner);
+            }
+            node /= 2;
+        }
+        tree[0] = winner;
+    }
+}
+
+pub fn multiway_mergesort_par<T, F>(v: &mut [T], is_less: F)
+where
+    T: Send + Sync + Default,
+    F: Fn(&T, &T) -> bool + Sync,
+{
+    let len = v.len();
+    let parts = rayon::current_num_threads();
+    if len < MERGESORT_MIN || parts == 1 {
+        v.sort_by(|a, b| compare(&is_less, a, b));
+        return;
+    }
+
+    let block = len.div_ceil(parts);
+    v.par_chunks_mut(block)
+        .for_each(|run| run.sort_by(|a, b| compare(&is_less, a, b)));
+
+    // Split points of every run at the start of every part of the output
+    let bounds: Vec<Vec<usize>> = {
+        let runs: Vec<&[T]> = v.chunks(block).collect();
+        (0..=parts)
+            .into_par_iter()
+            .map(|q| co_rank(&runs[..], std::cmp::min(q * block, len), &is_less))
+            .collect()
+    };
+
+    // Pieces of every run, regrouped by part of the output
+    let mut pieces: Vec<Vec<&mut [T]>> = (0..parts).map(|_| Vec::new()).collect();
+    for (j, run) in v.chunks_mut(block).enumerate() {
+        let mut rest = run;
+        for q in 0..parts {
+            let (head, tail) = rest.split_at_mut(bounds[q + 1][j] - bounds[q][j]);
+            pieces[q].push(head);
+            rest = tail;
+        }
+    }
+
+    let mut scratch: Vec<T> = (0..len).into_par_iter().map(|_| T::default()).collect();
+    scratch
+        .par_chunks_mut(block)
+        .zip(pieces.into_par_iter())
+        .for_each(|(dest, pieces)| merge_into(pieces, dest, &is_less));
+
+    v.par_chunks_mut(block)
+        .zip(scratch.par_chunks_mut(block))
+        .for_each(|(part, scratch)| part.swap_with_slice(scratch));
+}
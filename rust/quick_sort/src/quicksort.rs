@@ -0,0 +1,254 @@
+use rayon::prelude::*;
+
+/*************************************
+ * Parallel quicksort
+ *************************************/
+// A textbook quicksort: the pivot is the median of three elements, or the
+// ninther (median of three medians of three) on longer slices, and is moved
+// to the front before partitioning. The two sides of a partition are sorted
+// with `rayon::join` until they get shorter than the sequential cutoff, then
+// serially, recursing into the shorter side so that the stack stays small.
+//
+// A side that comes right of a pivot only holds elements at least as large as
+// that pivot. If its own pivot is no larger, the elements equal to it are the
+// smallest ones and are put aside in one partition, which keeps inputs with
+// few distinct values from degenerating.
+//
+// Partitioning is serial by default, making the top level a serial pass over
+// the whole input. With `par_partition` the slices longer than
+// PAR_PARTITION_MIN are cut into one block per thread, every block is
+// partitioned on its own and the blocks are gathered into a scratch buffer.
+
+// Slices at most this long are insertion sorted
+const INSERTION_CUTOFF: usize = 16;
+
+// Slices at least this long take the ninther as pivot
+const NINTHER_MIN: usize = 128;
+
+// Slices at least this long are partitioned in parallel, if asked for
+const PAR_PARTITION_MIN: usize = 1 << 16;
+
+pub fn insertion_sort<T, F>(v: &mut [T], is_less: &F)
+where
+    F: Fn(&T, &T) -> bool,
+{
+    for i in 1..v.len() {
+        let mut j = i;
+        while j > 0 && is_less(&v[j], &v[j - 1]) {
+            v.swap(j, j - 1);
+            j -= 1;
+        }
+    }
+}
+
+// Index of the median of v[a], v[b] and v[c]
+fn median3<T, F>(v: &[T], a: usize, b: usize, c: usize, is_less: &F) -> usize
+where
+    F: Fn(&T, &T) -> bool,
+{
+    let (a, b) = if is_less(&v[b], &v[a]) {
+        (b, a)
+    } else {
+        (a, b)
+    };
+    if is_less(&v[c], &v[a]) {
+        a
+    } else if is_less(&v[c], &v[b]) {
+        c
+    } else {
+        b
+    }
+}
+
+pub fn choose_pivot<T, F>(v: &[T], is_less: &F) -> usize
+where
+    F: Fn(&T, &T) -> bool,
+{
+    let len = v.len();
+    let mid = len / 2;
+
+    if len < NINTHER_MIN {
+        median3(v, 0, mid, len - 1, is_less)
+    } else {
+        let s = len / 8;
+        let a = median3(v, 0, s, 2 * s, is_less);
+        let b = median3(v, mid - s, mid, mid + s, is_less);
+        let c = median3(v, len - 1 - 2 * s, len - 1 - s, len - 1, is_less);
+        median3(v, a, b, c, is_less)
+    }
+}
+
+// Moves the elements going left before the others, returning their number
+fn partition_serial<T, P>(v: &mut [T], goes_left: &P) -> usize
+where
+    P: Fn(&T) -> bool,
+{
+    let mut i = 0;
+    let mut j = v.len();
+
+    loop {
+        while i < j && goes_left(&v[i]) {
+            i += 1;
+        }
+        while i < j && !goes_left(&v[j - 1]) {
+            j -= 1;
+        }
+        if i >= j {
+            return i;
+        }
+        v.swap(i, j - 1);
+        i += 1;
+        j -= 1;
+    }
+}
+
+// Moves the elements of every block of `v` going left before all the others,
+// both in the order of the blocks, through a scratch buffer. `lefts` is the
+// number of elements of every block going left and `goes_left(b, i)` whether
+// element i of block b does; returns the number going left.
+pub fn scatter_blocks<T, G>(v: &mut [T], block: usize, lefts: &[usize], goes_left: G) -> usize
+where
+    T: Send + Default,
+    G: Fn(usize, usize) -> bool + Sync,
+{
+    let len = v.len();
+    let total_left: usize = lefts.iter().sum();
+
+    // Every block gets a slice of the left and of the right side of scratch
+    let mut scratch: Vec<T> = (0..len).into_par_iter().map(|_| T::default()).collect();
+    let (mut left_dest, mut right_dest) = scratch.split_at_mut(total_left);
+    let mut dests = Vec::with_capacity(lefts.len());
+    for (part, &left) in v.chunks(block).zip(lefts.iter()) {
+        let (l, l_tail) = left_dest.split_at_mut(left);
+        let (r, r_tail) = right_dest.split_at_mut(part.len() - left);
+        dests.push((l, r));
+        left_dest = l_tail;
+        right_dest = r_tail;
+    }
+
+    v.par_chunks_mut(block)
+        .zip(dests.into_par_iter())
+        .enumerate()
+        .for_each(|(b, (part, (l, r)))| {
+            let (mut i, mut j) = (0, 0);
+            for (index, x) in part.iter_mut().enumerate() {
+                if goes_left(b, index) {
+                    std::mem::swap(x, &mut l[i]);
+                    i += 1;
+                } else {
+                    std::mem::swap(x, &mut r[j]);
+                    j += 1;
+                }
+            }
+        });
+    v.par_chunks_mut(block)
+        .zip(scratch.par_chunks_mut(block))
+        .for_each(|(part, scratch)| part.swap_with_slice(scratch));
+
+    total_left
+}
+
+fn partition_par<T, P>(v: &mut [T], goes_left: &P) -> usize
+where
+    T: Send + Default,
+    P: Fn(&T) -> bool + Sync,
+{
+    let len = v.len();
+    let block = len.div_ceil(rayon::current_num_threads());
+
+    let lefts: Vec<usize> = v
+        .par_chunks_mut(block)
+        .map(|part| partition_serial(part, goes_left))
+        .collect();
+    scatter_blocks(v, block, &lefts[..], |b, i| i < lefts[b])
+}
+
+// Partitions around the pivot at v[0], returning where the pivot ends up
+pub fn partition<T, P>(v: &mut [T], goes_left: P, parallel: bool) -> usize
+where
+    T: Send + Sync + Default,
+    P: Fn(&T, &T) -> bool + Sync,
+{
+    let (pivot, rest) = v.split_first_mut().unwrap();
+    let pivot = &*pivot;
+    let goes_left = |x: &T| goes_left(x, pivot);
+
+    let mid = if parallel && rest.len() >= PAR_PARTITION_MIN {
+        partition_par(rest, &goes_left)
+    } else {
+        partition_serial(rest, &goes_left)
+    };
+    v.swap(0, mid);
+    mid
+}
+
+// `pred` is the pivot left of `v`, if any, no larger than any element of v
+fn quicksort_rec<'a, T, F>(
+    mut v: &'a mut [T],
+    mut pred: Option<&'a T>,
+    is_less: &F,
+    cutoff: usize,
+    par_partition: bool,
+) where
+    T: Send + Sync + Default,
+    F: Fn(&T, &T) -> bool + Sync,
+{
+    loop {
+        let len = v.len();
+        if len <= INSERTION_CUTOFF {
+            insertion_sort(v, is_less);
+            return;
+        }
+
+        let pivot = choose_pivot(v, is_less);
+        v.swap(0, pivot);
+
+        // The pivot is equal to pred, and so are the smallest elements
+        if let Some(pred) = pred {
+            if !is_less(pred, &v[0]) {
+                let mid = partition(v, |x, pivot| !is_less(pivot, x), par_partition);
+                v = &mut v[(mid + 1)..];
+                continue;
+            }
+        }
+
+        let mid = partition(v, |x, pivot| is_less(x, pivot), par_partition);
+        let (left, rest) = v.split_at_mut(mid);
+        let (pivot, right) = rest.split_first_mut().unwrap();
+        let pivot = &*pivot;
+
+        if len > cutoff {
+            rayon::join(
+                || quicksort_rec(left, pred, is_less, cutoff, par_partition),
+                || quicksort_rec(right, Some(pivot), is_less, cutoff, par_partition),
+            );
+            return;
+        }
+
+        if left.len() < right.len() {
+            quicksort_rec(left, pred, is_less, cutoff, par_partition);
+            v = right;
+            pred = Some(pivot);
+        } else {
+            quicksort_rec(right, Some(pivot), is_less, cutoff, par_partition);
+            v = left;
+        }
+    }
+}
+
+pub fn quicksort_serial<T, F>(v: &mut [T], is_less: F)
+where
+    T: Send + Sync + Default,
+    F: Fn(&T, &T) -> bool + Sync,
+{
+    quicksort_rec(v, None, &is_less, usize::MAX, false);
+}
+
+// Slices longer than `cutoff` are split between two tasks
+pub fn quicksort_par<T, F>(v: &mut [T], is_less: F, cutoff: usize, par_partition: bool)
+where
+    T: Send + Sync + Default,
+    F: Fn(&T, &T) -> bool + Sync,
+{
+    quicksort_rec(v, None, &is_less, cutoff, par_partition);
+}
@@ -0,0 +1,128 @@
+use rayon::prelude::*;
+use std::cmp::Ordering;
+
+/*************************************
+ * Parallel samplesort
+ *************************************/
+// The input is split into one bucket per thread by `threads - 1` splitters,
+// picked at regular ranks from a sorted sample OVERSAMPLING times larger so
+// that the buckets come out about the same size. Every block of the input
+// finds the bucket of each of its elements by binary search among the
+// splitters; the bucket sizes per block say where the block writes its
+// elements in a scratch buffer, the blocks scatter in parallel and the
+// buckets are sorted in parallel, one task each. Elements equal to a splitter
+// all go to the same bucket, so inputs with few distinct values give
+// unbalanced buckets.
+
+// Sample elements per bucket
+const OVERSAMPLING: usize = 16;
+
+// Inputs shorter than this are sorted serially
+const SAMPLESORT_MIN: usize = 1 << 12;
+
+// The ordering given by a less-than function
+pub fn compare<T, F>(is_less: &F, a: &T, b: &T) -> Ordering
+where
+    F: Fn(&T, &T) -> bool,
+{
+    if is_less(a, b) {
+        Ordering::Less
+    } else if is_less(b, a) {
+        Ordering::Greater
+    } else {
+        Ordering::Equal
+    }
+}
+
+// Splitters at regular ranks of a sorted sample of the input
+fn choose_splitters<T, F>(v: &[T], buckets: usize, is_less: &F) -> Vec<T>
+where
+    T: Clone,
+    F: Fn(&T, &T) -> bool,
+{
+    let sample_len = OVERSAMPLING * buckets;
+    let mut sample: Vec<T> = (0..sample_len)
+        .map(|i| v[(2 * i + 1) * v.len() / (2 * sample_len)].clone())
+        .collect();
+    sample.sort_unstable_by(|a, b| compare(is_less, a, b));
+
+    (1..buckets)
+        .map(|i| sample[i * OVERSAMPLING].clone())
+        .collect()
+}
+
+pub fn samplesort_par<T, F>(v: &mut [T], is_less: F)
+where
+    T: Send + Sync + Default + Clone,
+    F: Fn(&T, &T) -> bool + Sync,
+{
+    let len = v.len();
+    let buckets = rayon::current_num_threads();
+    if len < SAMPLESORT_MIN || buckets == 1 {
+        v.sort_unstable_by(|a, b| compare(&is_less, a, b));
+        return;
+    }
+
+    let splitters = choose_splitters(v, buckets, &is_less);
+    let block = len.div_ceil(buckets);
+
+    // Bucket of every element and bucket sizes, per block
+    let classes: Vec<(Vec<usize>, Vec<usize>)> = v
+        .par_chunks(block)
+        .map(|part| {
+            let mut counts = vec![0; buckets];
+            let bucket_of: Vec<usize> = part
+                .iter()
+                .map(|x| {
+                    let bucket = splitters.partition_point(|s| !is_less(x, s));
+                    counts[bucket] += 1;
+                    bucket
+                })
+                .collect();
+            (bucket_of, counts)
+        })
+        .collect();
+
+    // Slices of scratch per block and bucket, cut in bucket-major order
+    let mut scratch: Vec<T> = (0..len).into_par_iter().map(|_| T::default()).collect();
+    let mut dests: Vec<Vec<&mut [T]>> = (0..classes.len())
+        .map(|_| Vec::with_capacity(buckets))
+        .collect();
+    let mut bucket_lens = vec![0; buckets];
+    let mut rest = &mut scratch[..];
+    for (bucket, bucket_len) in bucket_lens.iter_mut().enumerate() {
+        for (b, (_, counts)) in classes.iter().enumerate() {
+            let (head, tail) = rest.split_at_mut(counts[bucket]);
+            dests[b].push(head);
+            rest = tail;
+            *bucket_len += counts[bucket];
+        }
+    }
+
+    v.par_chunks_mut(block)
+        .zip(classes.par_iter())
+        .zip(dests.into_par_iter())
+        .for_each(|((part, (bucket_of, _)), mut dests)| {
+            let mut next = vec![0; buckets];
+            for (x, &bucket) in part.iter_mut().zip(bucket_of.iter()) {
+                std::mem::swap(x, &mut dests[bucket][next[bucket]]);
+                next[bucket] += 1;
+            }
+        });
+
+    // Sort the buckets, one task each
+    let mut bucket_slices = Vec::with_capacity(buckets);
+    let mut rest = &mut scratch[..];
+    for &bucket_len in bucket_lens.iter() {
+        let (head, tail) = rest.split_at_mut(bucket_len);
+        bucket_slices.push(head);
+        rest = tail;
+    }
+    bucket_slices
+        .into_par_iter()
+        .for_each(|bucket| bucket.sort_unstable_by(|a, b| compare(&is_less, a, b)));
+
+    v.par_chunks_mut(block)
+        .zip(scratch.par_chunks_mut(block))
+        .for_each(|(part, scratch)| part.swap_with_slice(scratch));
+}
@@ -0,0 +1,339 @@
+use crate::element::SortElement;
+use crate::quicksort::{choose_pivot, partition, scatter_blocks};
+use crate::samplesort::compare;
+use crate::{as_ms, time_runs, QSConfig};
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::time::Duration;
+
+// Slices at least this long are partitioned in parallel by the quickselect
+const SELECT_PAR_MIN: usize = 1 << 16;
+
+/*************************************
+ * Selection, top-k and partition
+ *************************************/
+// The operations around sorting that do not need a full sort.
+//
+// select_nth_par is a quickselect: every round picks a pivot as the quicksort
+// does, partitions the slice around it in parallel and keeps the side holding
+// rank k, until the slice is shorter than SELECT_PAR_MIN and the standard
+// library selects in the rest. As in the quicksort, a right side whose pivot
+// is no larger than the pivot left of it puts the elements equal to it aside,
+// so that inputs with few distinct values still shrink.
+//
+// top_k_par keeps the k first elements of every block of the input, one block
+// per thread, in a heap whose top is the last of them, and merges the heaps
+// with a rayon reduce.
+//
+// stable_partition_par finds which elements of every block satisfy the
+// predicate and counts them; the counts say where every block writes its
+// elements of each side in a scratch buffer, which keeps their order.
+
+pub fn select_nth_par<T, F>(mut v: &mut [T], mut k: usize, is_less: F)
+where
+    T: Send + Sync + Default,
+    F: Fn(&T, &T) -> bool + Sync,
+{
+    assert!(k < v.len());
+    let mut pred: Option<&T> = None;
+    // One block would only go through the scratch buffer for nothing
+    let parallel = rayon::current_num_threads() > 1;
+
+    while v.len() >= SELECT_PAR_MIN {
+        let pivot = choose_pivot(v, &is_less);
+        v.swap(0, pivot);
+
+        // The pivot is equal to pred, and so are the smallest elements
+        if let Some(pred) = pred {
+            if !is_less(pred, &v[0]) {
+                let mid = partition(v, |x, pivot| !is_less(pivot, x), parallel);
+                if k <= mid {
+                    return;
+                }
+                v = &mut v[(mid + 1)..];
+                k -= mid + 1;
+                continue;
+            }
+        }
+
+        let mid = partition(v, |x, pivot| is_less(x, pivot), parallel);
+        if k == mid {
+            return;
+        }
+        let (left, rest) = v.split_at_mut(mid);
+        let (pivot, right) = rest.split_first_mut().unwrap();
+        if k < mid {
+            v = left;
+        } else {
+            v = right;
+            k -= mid + 1;
+            pred = Some(&*pivot);
+        }
+    }
+
+    v.select_nth_unstable_by(k, |a, b| compare(&is_less, a, b));
+}
+
+// An element of the input ordered by a less-than function, for the heaps
+struct ByLess<'a, T, F> {
+    x: &'a T,
+    is_less: &'a F,
+}
+
+impl<'a, T, F: Fn(&T, &T) -> bool> PartialEq for ByLess<'a, T, F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<'a, T, F: Fn(&T, &T) -> bool> Eq for ByLess<'a, T, F> {}
+
+impl<'a, T, F: Fn(&T, &T) -> bool> PartialOrd for ByLess<'a, T, F> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a, T, F: Fn(&T, &T) -> bool> Ord for ByLess<'a, T, F> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare(self.is_less, self.x, other.x)
+    }
+}
+
+type TopHeap<'a, T, F> = BinaryHeap<ByLess<'a, T, F>>;
+
+// Pushes into a heap of the k first elements seen so far
+fn push_bounded<'a, T, F>(heap: &mut TopHeap<'a, T, F>, item: ByLess<'a, T, F>, k: usize)
+where
+    F: Fn(&T, &T) -> bool,
+{
+    if heap.len() < k {
+        heap.push(item);
+    } else if let Some(mut top) = heap.peek_mut() {
+        if item < *top {
+            *top = item;
+        }
+    }
+}
+
+fn top_k_heap<'a, T, F>(part: &'a [T], k: usize, is_less: &'a F) -> TopHeap<'a, T, F>
+where
+    F: Fn(&T, &T) -> bool,
+{
+    let mut heap = BinaryHeap::with_capacity(k + 1);
+    for x in part.iter() {
+        push_bounded(&mut heap, ByLess { x, is_less }, k);
+    }
+    heap
+}
+
+fn sorted_top<T: Clone, F>(heap: TopHeap<T, F>) -> Vec<T>
+where
+    F: Fn(&T, &T) -> bool,
+{
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|item| item.x.clone())
+        .collect()
+}
+
+pub fn top_k_serial<T, F>(v: &[T], k: usize, is_less: F) -> Vec<T>
+where
+    T: Clone,
+    F: Fn(&T, &T) -> bool,
+{
+    sorted_top(top_k_heap(v, k, &is_less))
+}
+
+pub fn top_k_par<T, F>(v: &[T], k: usize, is_less: F) -> Vec<T>
+where
+    T: Sync + Clone,
+    F: Fn(&T, &T) -> bool + Sync,
+{
+    let block = std::cmp::max(1, v.len().div_ceil(rayon::current_num_threads()));
+    let heap = v
+        .par_chunks(block)
+        .map(|part| top_k_heap(part, k, &is_less))
+        .reduce(BinaryHeap::new, |a, b| {
+            let (mut big, small) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+            for item in small.into_iter() {
+                push_bounded(&mut big, item, k);
+            }
+            big
+        });
+    sorted_top(heap)
+}
+
+// Moves the elements satisfying `pred` before the others, both in their
+// order, keeping the first ones in place and the others in a buffer; returns
+// the number of elements satisfying `pred`
+pub fn stable_partition_serial<T, P>(v: &mut [T], pred: P) -> usize
+where
+    T: Default,
+    P: Fn(&T) -> bool,
+{
+    let mut right = Vec::new();
+    let mut left = 0;
+
+    for i in 0..v.len() {
+        if pred(&v[i]) {
+            v.swap(left, i);
+            left += 1;
+        } else {
+            right.push(std::mem::take(&mut v[i]));
+        }
+    }
+    for (slot, x) in v[left..].iter_mut().zip(right) {
+        *slot = x;
+    }
+
+    left
+}
+
+// The same in parallel, every block moving its elements to its slices of
+// both sides through a scratch buffer
+pub fn stable_partition_par<T, P>(v: &mut [T], pred: P) -> usize
+where
+    T: Send + Sync + Default,
+    P: Fn(&T) -> bool + Sync,
+{
+    let block = std::cmp::max(1, v.len().div_ceil(rayon::current_num_threads()));
+
+    // Side of every element and number going left, per block
+    let classes: Vec<(Vec<bool>, usize)> = v
+        .par_chunks(block)
+        .map(|part| {
+            let goes_left: Vec<bool> = part.iter().map(&pred).collect();
+            let left = goes_left.iter().filter(|&&left| left).count();
+            (goes_left, left)
+        })
+        .collect();
+    let lefts: Vec<usize> = classes.iter().map(|(_, left)| *left).collect();
+
+    scatter_blocks(v, block, &lefts[..], |b, i| classes[b].0[i])
+}
+
+/*************************************
+ * Benchmark
+ *************************************/
+// With --select every operation is timed serially and in parallel, and
+// against a par_sort_unstable of the whole input followed by slicing: the
+// element at rank --nth, the --top_k first elements, and the partition by
+// being before the element at rank --nth, in the order of --sort.
+
+fn print_times(qs_config: &QSConfig, name: &str, serial: Duration, par: Duration, sort: Duration) {
+    if (qs_config.code_config == 0) || (qs_config.code_config == 2) {
+        println!("[{} serial]: \t[{:?}] ms", name, as_ms(serial));
+    }
+    if (qs_config.code_config == 0) || (qs_config.code_config == 1) {
+        println!("[{} par]: \t\t[{:?}] ms", name, as_ms(par));
+        if qs_config.code_config == 0 {
+            println!(
+                "++++ \t\t({:.2}x speedup from {:?} threads)",
+                serial.as_micros() as f64 / par.as_micros() as f64,
+                qs_config.num_threads
+            );
+        }
+        println!(
+            "++++ \t\t({:.2}x faster than sorting)",
+            sort.as_micros() as f64 / par.as_micros() as f64
+        );
+    }
+    println!();
+}
+
+pub fn do_select_runs<T: SortElement>(qs_config: &QSConfig, v_orig: &[T]) {
+    let len = v_orig.len();
+    let nth = qs_config.nth as usize;
+    let top_k = qs_config.top_k as usize;
+    let descending = qs_config.sort_order == 1;
+    let is_less = |a: &T, b: &T| if descending { b < a } else { a < b };
+    let serial = qs_config.code_config != 1;
+
+    let (sort_time, v_sorted) = time_runs(qs_config, v_orig, |v| {
+        v.par_sort_unstable_by(|a, b| compare(&is_less, a, b));
+        std::mem::take(v)
+    });
+    println!("[sort-unstable par]: \t\t[{:?}] ms\n", as_ms(sort_time));
+
+    // Selection
+    let serial_time = if serial {
+        time_runs(qs_config, v_orig, |v| {
+            v.select_nth_unstable_by(nth, |a, b| compare(&is_less, a, b));
+        })
+        .0
+    } else {
+        Duration::new(0, 0)
+    };
+    let (par_time, selected) = time_runs(qs_config, v_orig, |v| {
+        select_nth_par(&mut v[..], nth, is_less);
+        std::mem::take(v)
+    });
+    print_times(qs_config, "select-nth", serial_time, par_time, sort_time);
+
+    // Top k
+    let serial_time = if serial {
+        time_runs(qs_config, v_orig, |v| top_k_serial(&v[..], top_k, is_less)).0
+    } else {
+        Duration::new(0, 0)
+    };
+    let (par_time, top) = time_runs(qs_config, v_orig, |v| top_k_par(&v[..], top_k, is_less));
+    print_times(qs_config, "select-top-k", serial_time, par_time, sort_time);
+
+    // Stable partition around the element at rank nth
+    let pivot = &v_sorted[nth];
+    let goes_left = |x: &T| is_less(x, pivot);
+    let serial_time = if serial {
+        time_runs(qs_config, v_orig, |v| {
+            stable_partition_serial(&mut v[..], goes_left)
+        })
+        .0
+    } else {
+        Duration::new(0, 0)
+    };
+    let (par_time, (partitioned, left)) = time_runs(qs_config, v_orig, |v| {
+        let left = stable_partition_par(&mut v[..], goes_left);
+        (std::mem::take(v), left)
+    });
+    print_times(
+        qs_config,
+        "select-partition",
+        serial_time,
+        par_time,
+        sort_time,
+    );
+
+    if qs_config.verify {
+        assert!(
+            selected[nth] == v_sorted[nth]
+                && selected[..nth]
+                    .par_iter()
+                    .all(|x| !is_less(&selected[nth], x))
+                && selected[nth..]
+                    .par_iter()
+                    .all(|x| !is_less(x, &selected[nth])),
+            "select-nth: element at rank {} not selected",
+            nth
+        );
+        assert!(
+            top.len() == std::cmp::min(top_k, len) && top[..] == v_sorted[..top.len()],
+            "select-top-k: not the {} first elements",
+            top_k
+        );
+        // The input split by the predicate in two passes, in order
+        let expected = v_orig
+            .iter()
+            .filter(|x| goes_left(x))
+            .chain(v_orig.iter().filter(|x| !goes_left(x)));
+        assert!(
+            left == v_sorted.partition_point(goes_left)
+                && partitioned
+                    .iter()
+                    .zip(expected)
+                    .all(|(x, y)| x.fingerprint() == y.fingerprint()),
+            "select-partition: not a stable partition"
+        );
+        println!("All outputs verified");
+    }
+}
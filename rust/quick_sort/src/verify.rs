@@ -0,0 +1,106 @@
+use crate::element::{SortElement, Tagged};
+use crate::{is_sorted, QSConfig};
+use rayon::prelude::*;
+
+/*************************************
+ * Verification of the results
+ *************************************/
+// With --verify every sorted output is checked to be in order and to be a
+// permutation of the input. The permutation check compares a multiset hash
+// of the input and of the output: the wrapping sums of h(x) and h(x)^2 over
+// the elements, with h a mix of the fingerprint of the element, which do not
+// depend on the order of the elements and are summed in parallel. A sort that
+// loses, duplicates or corrupts an element changes the sums but for a
+// collision of 128 bits.
+//
+// The stable sorts are also run on the input tagged with the index of every
+// element, compared by element only, and the indices of every run of equal
+// elements must come out increasing.
+
+// The finalizer of splitmix64, so that close fingerprints hash far apart
+fn mix(mut x: u64) -> u64 {
+    x ^= x >> 30;
+    x = x.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x ^= x >> 27;
+    x = x.wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+// Sums of two multiset hashes, the hash of the union of the multisets
+pub fn combine(a: (u64, u64), b: (u64, u64)) -> (u64, u64) {
+    (a.0.wrapping_add(b.0), a.1.wrapping_add(b.1))
+}
+
+// Multiset hash of the elements of the given fingerprints
+pub fn hash_fingerprints<I: ParallelIterator<Item = u64>>(fingerprints: I) -> (u64, u64) {
+    fingerprints
+        .map(|fingerprint| {
+            let h = mix(fingerprint);
+            (h, h.wrapping_mul(h))
+        })
+        .reduce(|| (0, 0), combine)
+}
+
+pub fn multiset_hash<T: SortElement>(v: &[T]) -> (u64, u64) {
+    hash_fingerprints(v.par_iter().map(|x| x.fingerprint()))
+}
+
+// Order and permutation of the output of `name`
+pub fn check<T: SortElement>(qs_config: &QSConfig, name: &str, input_hash: (u64, u64), v: &[T]) {
+    if !qs_config.verify {
+        return;
+    }
+    assert!(
+        is_sorted(v, qs_config.sort_order),
+        "{}: output not sorted",
+        name
+    );
+    assert!(
+        multiset_hash(v) == input_hash,
+        "{}: output not a permutation of the input",
+        name
+    );
+}
+
+// Order of the equal elements after the stable sort `sort`
+pub fn check_stable<T: SortElement>(
+    qs_config: &QSConfig,
+    name: &str,
+    v_orig: &[T],
+    sort: fn(&QSConfig, &mut [Tagged<T>]),
+) {
+    if !qs_config.verify {
+        return;
+    }
+    let mut tagged: Vec<Tagged<T>> = v_orig
+        .par_iter()
+        .cloned()
+        .enumerate()
+        .map(|(index, value)| Tagged { value, index })
+        .collect();
+    sort(qs_config, &mut tagged[..]);
+
+    assert!(
+        is_sorted(&tagged[..], qs_config.sort_order),
+        "{}: tagged output not sorted",
+        name
+    );
+    let tie = |&i: &usize| tagged[i - 1].value == tagged[i].value;
+    let out_of_order = (1..tagged.len())
+        .into_par_iter()
+        .filter(tie)
+        .find_first(|&i| tagged[i - 1].index > tagged[i].index);
+    if let Some(i) = out_of_order {
+        panic!(
+            "{}: elements {} and {} with equal keys out of order",
+            name,
+            tagged[i - 1].index,
+            tagged[i].index
+        );
+    }
+    let ties = (1..tagged.len()).into_par_iter().filter(tie).count();
+    println!(
+        "++++ \t\t({}: stable over {} pairs of equal keys)",
+        name, ties
+    );
+}
@@ -1,3 +1,279 @@
+#[macro_use]
+extern crate clap;
+extern crate num_cpus;
+extern crate rand;
+extern crate rayon;
+
+mod radix;
+
+use clap::{App, Arg};
+use radix::{sort_key, RadixKey};
+use rand::distributions::{Distribution, Uniform};
+use rand::{thread_rng, Rng};
+use rayon::prelude::*;
+use std::time::{Duration, Instant};
+
+// A radix sort called with (keys, radix bits, descending)
+type RadixSort<K> = fn(&mut [K], u32, bool);
+
 fn main() {
-    println!("Hello, world!");
+    let rs_config = parse_arguments();
+
+    // Set the number of threads for rayon
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(rs_config.num_threads as usize)
+        .build_global()
+        .unwrap();
+
+    // Signed and floating point keys are spread around zero
+    match rs_config.key_type {
+        KeyType::U32 => do_runs::<u32, _>(&rs_config, Uniform::new_inclusive(u32::MIN, u32::MAX)),
+        KeyType::U64 => do_runs::<u64, _>(&rs_config, Uniform::new_inclusive(u64::MIN, u64::MAX)),
+        KeyType::I64 => do_runs::<i64, _>(&rs_config, Uniform::new_inclusive(i64::MIN, i64::MAX)),
+        KeyType::F64 => do_runs::<f64, _>(&rs_config, Uniform::new(-1e9, 1e9)),
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum KeyType {
+    U32,
+    U64,
+    I64,
+    F64,
+}
+
+// Configuration file, reflects command line options
+#[derive(Copy, Clone)]
+pub struct RSConfig {
+    pub num_elements: u32,
+    pub sort_order: u32,
+    pub num_of_runs: u32,
+    pub num_threads: u32,
+    pub code_config: u32,
+    pub key_type: KeyType,
+    pub radix_bits: u32,
+}
+
+pub fn parse_arguments() -> RSConfig {
+    // Create arugment matches
+    let matches = App::new("Radix_Sort")
+        .version("1.0")
+        .author("Nishal & Supradeep")
+        // Argument Parsing for all arguments of Radix sort
+        .arg(
+            Arg::with_name("NUM_ELEMENTS")
+                .short("n")
+                .long("num_vals")
+                .value_name("NUM_ELEMENTS")
+                .help("number of elements in the array to sort (default: 1M))"),
+        )
+        .arg(
+            Arg::with_name("SORT_ORDER")
+                .short("s")
+                .long("sort")
+                .value_name("SORT_ORDER")
+                .help("enter 0 to sort ascending, 1 for descending (default: 0))"),
+        )
+        .arg(
+            Arg::with_name("NUM_OF_RUNS")
+                .short("r")
+                .long("runs")
+                .value_name("NUM_OF_RUNS")
+                .help("number of repetitive runs (default: 1)"),
+        )
+        .arg(
+            Arg::with_name("NUMBER_OF_THREADS")
+                .short("t")
+                .long("num_threads")
+                .value_name("NUMBER_OF_THREADS")
+                .help("number of threads to use (default: MAX_CPUS)"),
+        )
+        .arg(
+            Arg::with_name("CODE")
+                .short("c")
+                .long("code")
+                .value_name("CODE")
+                .help("Enter 0 for all code, 1 for parallel only, 2 for serial only (default: 0)"),
+        )
+        .arg(
+            Arg::with_name("KEY_TYPE")
+                .long("type")
+                .value_name("KEY_TYPE")
+                .possible_values(&["u32", "u64", "i64", "f64"])
+                .help("type of the keys to sort (default: u64)"),
+        )
+        .arg(
+            Arg::with_name("RADIX_BITS")
+                .short("b")
+                .long("radix_bits")
+                .value_name("RADIX_BITS")
+                .help("bits of the key sorted by every pass, 1 to 16 (default: 8)"),
+        )
+        .get_matches();
+
+    // Find number of cpus available
+    let max_threads = num_cpus::get();
+
+    // Match and store all values of the arguments
+    let num_elements = value_t!(matches.value_of("NUM_ELEMENTS"), u32).unwrap_or(1000000);
+    let sort_order = value_t!(matches.value_of("SORT_ORDER"), u32).unwrap_or(0);
+    let num_of_runs = value_t!(matches.value_of("NUM_OF_RUNS"), u32).unwrap_or(3);
+    let num_threads =
+        value_t!(matches.value_of("NUMBER_OF_THREADS"), u32).unwrap_or(max_threads as u32);
+    let code_config = value_t!(matches.value_of("CODE"), u32).unwrap_or(0);
+    let key_type = match matches.value_of("KEY_TYPE").unwrap_or("u64") {
+        "u32" => KeyType::U32,
+        "i64" => KeyType::I64,
+        "f64" => KeyType::F64,
+        _ => KeyType::U64,
+    };
+    let radix_bits = value_t!(matches.value_of("RADIX_BITS"), u32).unwrap_or(8);
+
+    // Check if values are correct for the radix sort program
+    assert!(num_elements > 0);
+    assert!((sort_order == 0) || (sort_order == 1));
+    assert!(num_threads > 0);
+    assert!(num_of_runs > 0);
+    assert!(code_config < 3);
+    assert!((1..=16).contains(&radix_bits));
+
+    println!("Configuration: \nnum_elements: {}, sort_order: {}, num_threads: {}, num_of_runs: {}, code_config: {}, key_type: {:?}, radix_bits: {}\n",
+        num_elements, sort_order, num_threads, num_of_runs, code_config, key_type, radix_bits);
+
+    // Return the struct that can be used by the functions
+    RSConfig {
+        num_elements,
+        sort_order,
+        num_threads,
+        num_of_runs,
+        code_config,
+        key_type,
+        radix_bits,
+    }
+}
+
+// Sorts a copy of `v_orig` on every run, printing the best time, and returns
+// it with the last sorted copy
+fn time_sort<K, F>(name: &str, num_runs: u32, v_orig: &[K], mut sort: F) -> (Duration, Vec<K>)
+where
+    K: RadixKey,
+    F: FnMut(&mut [K]),
+{
+    let mut best_time = Duration::new(10000, 0);
+    let mut v = v_orig.to_vec();
+
+    for _ in 0..num_runs {
+        v.copy_from_slice(v_orig);
+        let start = Instant::now();
+        sort(&mut v[..]);
+        let end = Instant::now();
+
+        best_time = std::cmp::min(best_time, end.duration_since(start));
+    }
+
+    println!(
+        "[{}]: \t[{:?}] ms",
+        name,
+        best_time.as_micros() as f64 / 1000_f64
+    );
+
+    (best_time, v)
+}
+
+fn print_speedup(rs_config: &RSConfig, serial_time: Duration, par_time: Duration) {
+    println!(
+        "++++ \t\t({:.2}x speedup from {:?} threads)\n",
+        serial_time.as_micros() as f64 / par_time.as_micros() as f64,
+        rs_config.num_threads
+    );
+}
+
+// Both sorts must give the same keys, in order
+fn check_sorted<K: RadixKey>(name: &str, descending: bool, v: &[K], expected: &[K]) {
+    assert!(
+        v.iter()
+            .zip(expected.iter())
+            .all(|(x, y)| sort_key(*x, descending) == sort_key(*y, descending)),
+        "{} gave a wrong result",
+        name
+    );
+}
+
+pub fn do_runs<K, D>(rs_config: &RSConfig, dist: D)
+where
+    K: RadixKey,
+    D: Distribution<K>,
+{
+    let num_runs = rs_config.num_of_runs;
+    let radix_bits = rs_config.radix_bits;
+    let descending = rs_config.sort_order == 1;
+
+    let mut unstable_serial_time = Duration::new(10000, 0);
+    let mut radix_serial_times = [Duration::new(10000, 0); 2];
+
+    // LSD moves the elements between the input and a scratch buffer, MSD
+    // permutes them in place
+    let radix_sorts: [(&str, RadixSort<K>, RadixSort<K>); 2] = [
+        ("lsd", radix::lsd_sort_serial, radix::lsd_sort_par),
+        ("msd", radix::msd_sort_serial, radix::msd_sort_par),
+    ];
+
+    let v_orig: Vec<K> = thread_rng()
+        .sample_iter(&dist)
+        .take(rs_config.num_elements as usize)
+        .collect();
+    let mut expected = v_orig.clone();
+    expected.par_sort_unstable_by_key(|x| sort_key(*x, descending));
+
+    if (rs_config.code_config == 0) || (rs_config.code_config == 2) {
+        // Comparison sort of the standard library
+        let (time, _) = time_sort("sort-unstable-rust serial", num_runs, &v_orig[..], |v| {
+            v.sort_unstable_by_key(|x| sort_key(*x, descending))
+        });
+        unstable_serial_time = time;
+
+        for ((name, serial_sort, _), serial_time) in
+            radix_sorts.iter().zip(radix_serial_times.iter_mut())
+        {
+            let (time, v) = time_sort(
+                &format!("sort-{}-rust serial", name),
+                num_runs,
+                &v_orig[..],
+                |v| serial_sort(v, radix_bits, descending),
+            );
+            check_sorted(
+                &format!("{} serial", name),
+                descending,
+                &v[..],
+                &expected[..],
+            );
+            *serial_time = time;
+        }
+        println!();
+    }
+
+    if (rs_config.code_config == 0) || (rs_config.code_config == 1) {
+        let (unstable_par_time, _) = time_sort("sort-unstable par", num_runs, &v_orig[..], |v| {
+            v.par_sort_unstable_by_key(|x| sort_key(*x, descending))
+        });
+        if rs_config.code_config == 0 {
+            print_speedup(rs_config, unstable_serial_time, unstable_par_time);
+        }
+
+        for ((name, _, par_sort), serial_time) in radix_sorts.iter().zip(radix_serial_times.iter())
+        {
+            let (par_time, v) =
+                time_sort(&format!("sort-{} par", name), num_runs, &v_orig[..], |v| {
+                    par_sort(v, radix_bits, descending)
+                });
+            check_sorted(&format!("{} par", name), descending, &v[..], &expected[..]);
+            println!(
+                "++++ \t\t({:.2}x faster than par_sort_unstable)",
+                unstable_par_time.as_micros() as f64 / par_time.as_micros() as f64
+            );
+            if rs_config.code_config == 0 {
+                print_speedup(rs_config, *serial_time, par_time);
+            }
+        }
+    }
 }
@@ -0,0 +1,385 @@
+use rayon::prelude::*;
+
+/*************************************
+ * Radix keys
+ *************************************/
+// Every key type maps to an unsigned integer of BITS bits with the same
+// order, and the sort works on the digits of that integer.
+pub trait RadixKey: Copy + Default + Send + Sync {
+    const BITS: u32;
+
+    fn key(self) -> u64;
+}
+
+impl RadixKey for u32 {
+    const BITS: u32 = 32;
+
+    fn key(self) -> u64 {
+        self as u64
+    }
+}
+
+impl RadixKey for u64 {
+    const BITS: u32 = 64;
+
+    fn key(self) -> u64 {
+        self
+    }
+}
+
+// Flipping the sign bit puts the negative numbers first
+impl RadixKey for i64 {
+    const BITS: u32 = 64;
+
+    fn key(self) -> u64 {
+        (self as u64) ^ (1 << 63)
+    }
+}
+
+// IEEE 754 total order: positive numbers get the sign bit set, negative ones
+// have all their bits flipped so that larger magnitudes come first
+impl RadixKey for f64 {
+    const BITS: u32 = 64;
+
+    fn key(self) -> u64 {
+        let bits = self.to_bits();
+        if bits >> 63 == 1 {
+            !bits
+        } else {
+            bits | (1 << 63)
+        }
+    }
+}
+
+// The key of `x` in the requested order, descending keys being complemented
+pub fn sort_key<K: RadixKey>(x: K, descending: bool) -> u64 {
+    if descending {
+        !x.key() & (u64::MAX >> (64 - K::BITS))
+    } else {
+        x.key()
+    }
+}
+
+/*************************************
+ * LSD radix sort
+ *************************************/
+// Every pass sorts stably on `radix_bits` bits of the key, from the least
+// significant ones up, moving the elements between the input and a scratch
+// buffer of the same size. A pass counts the digits into a histogram, turns
+// the histogram into the offset of every bucket and scatters the elements to
+// their bucket. Passes where all the elements have the same digit are
+// skipped, which makes u32 keys and small ranges cheaper.
+//
+// The parallel pass cuts the input into one chunk per thread. Every chunk
+// gets its own histogram; the elements of digit d from chunk c go after those
+// of smaller digits and after those of digit d from chunks before c, so the
+// running sum of the histograms in digit-major order gives every chunk a
+// disjoint slice of the output per digit, and the chunks scatter in parallel.
+//
+// That running sum is itself parallel: the digits are split into one block
+// per thread, the start of every block in the output is the running sum of
+// the block totals, and every block then walks its own digits and chunks to
+// cut its part of the output.
+
+fn digit<K: RadixKey>(x: K, descending: bool, shift: u32, mask: u64) -> usize {
+    ((sort_key(x, descending) >> shift) & mask) as usize
+}
+
+fn histogram<K: RadixKey>(src: &[K], descending: bool, shift: u32, mask: u64) -> Vec<usize> {
+    let mut counts = vec![0; mask as usize + 1];
+    src.iter()
+        .for_each(|&x| counts[digit(x, descending, shift, mask)] += 1);
+    counts
+}
+
+// Runs `pass` over every digit, returning whether the result is in scratch
+fn lsd_passes<K, F>(v: &mut [K], scratch: &mut [K], radix_bits: u32, mut pass: F)
+where
+    K: RadixKey,
+    F: FnMut(&[K], &mut [K], u32) -> bool,
+{
+    let mut src = v;
+    let mut dest = scratch;
+    let mut in_scratch = false;
+
+    for shift in (0..K::BITS).step_by(radix_bits as usize) {
+        if pass(src, dest, shift) {
+            std::mem::swap(&mut src, &mut dest);
+            in_scratch = !in_scratch;
+        }
+    }
+
+    // After an odd number of passes the sorted elements are in scratch
+    if in_scratch {
+        dest.copy_from_slice(src);
+    }
+}
+
+pub fn lsd_sort_serial<K: RadixKey>(v: &mut [K], radix_bits: u32, descending: bool) {
+    let mask = (1 << radix_bits) - 1;
+    let mut scratch = vec![K::default(); v.len()];
+    let n = v.len();
+
+    lsd_passes(v, &mut scratch[..], radix_bits, |src, dest, shift| {
+        let counts = histogram(src, descending, shift, mask);
+        if counts.contains(&n) {
+            return false;
+        }
+
+        let mut offsets = vec![0; counts.len()];
+        for d in 1..counts.len() {
+            offsets[d] = offsets[d - 1] + counts[d - 1];
+        }
+        for &x in src {
+            let d = digit(x, descending, shift, mask);
+            dest[offsets[d]] = x;
+            offsets[d] += 1;
+        }
+        true
+    });
+}
+
+// Slices of dest per chunk and digit, cut in digit-major order from the
+// histograms of the chunks and the totals of the digits. Every chunk gets its
+// slices as one list per block of digits.
+fn cut_buckets<'a, K: Send>(
+    dest: &'a mut [K],
+    histograms: &[Vec<usize>],
+    totals: &[usize],
+) -> Vec<Vec<Vec<&'a mut [K]>>> {
+    let block_digits = totals.len().div_ceil(rayon::current_num_threads());
+    let block_totals: Vec<usize> = totals
+        .par_chunks(block_digits)
+        .map(|block| block.iter().sum())
+        .collect();
+
+    let mut regions = Vec::with_capacity(block_totals.len());
+    let mut rest = dest;
+    for &total in block_totals.iter() {
+        let (head, tail) = rest.split_at_mut(total);
+        regions.push(head);
+        rest = tail;
+    }
+
+    let blocks: Vec<Vec<Vec<&mut [K]>>> = regions
+        .into_par_iter()
+        .enumerate()
+        .map(|(b, mut region)| {
+            let digits = (b * block_digits)..std::cmp::min((b + 1) * block_digits, totals.len());
+            let mut buckets: Vec<Vec<&mut [K]>> = (0..histograms.len())
+                .map(|_| Vec::with_capacity(digits.len()))
+                .collect();
+            for d in digits {
+                for (c, counts) in histograms.iter().enumerate() {
+                    let (head, tail) = region.split_at_mut(counts[d]);
+                    buckets[c].push(head);
+                    region = tail;
+                }
+            }
+            buckets
+        })
+        .collect();
+
+    let mut by_chunk: Vec<Vec<Vec<&mut [K]>>> = (0..histograms.len())
+        .map(|_| Vec::with_capacity(blocks.len()))
+        .collect();
+    for block in blocks {
+        for (c, buckets) in block.into_iter().enumerate() {
+            by_chunk[c].push(buckets);
+        }
+    }
+    by_chunk
+}
+
+pub fn lsd_sort_par<K: RadixKey>(v: &mut [K], radix_bits: u32, descending: bool) {
+    let mask = (1 << radix_bits) - 1;
+    let radix = mask as usize + 1;
+    let mut scratch = vec![K::default(); v.len()];
+    let n = v.len();
+    let chunk = std::cmp::max(1, n.div_ceil(rayon::current_num_threads()));
+
+    lsd_passes(v, &mut scratch[..], radix_bits, |src, dest, shift| {
+        let histograms: Vec<Vec<usize>> = src
+            .par_chunks(chunk)
+            .map(|part| histogram(part, descending, shift, mask))
+            .collect();
+        let totals: Vec<usize> = (0..radix)
+            .into_par_iter()
+            .map(|d| histograms.iter().map(|counts| counts[d]).sum())
+            .collect();
+        if totals.par_iter().any(|&total| total == n) {
+            return false;
+        }
+
+        let buckets = cut_buckets(dest, &histograms[..], &totals[..]);
+        src.par_chunks(chunk)
+            .zip(buckets.into_par_iter())
+            .for_each(|(part, blocks)| {
+                let mut buckets: Vec<&mut [K]> = blocks.into_iter().flatten().collect();
+                let mut next = vec![0; radix];
+                for &x in part {
+                    let d = digit(x, descending, shift, mask);
+                    buckets[d][next[d]] = x;
+                    next[d] += 1;
+                }
+            });
+        true
+    });
+}
+
+/*************************************
+ * In-place MSD radix sort
+ *************************************/
+// American flag sort: every level counts the digits of a bucket, then moves
+// the elements to their sub-bucket with swaps, following the cycles of the
+// permutation, and sorts the sub-buckets on the next digit down. There is no
+// scratch buffer, only a histogram per level. The sub-buckets are independent
+// and of any size: the parallel version counts the digits of a large bucket
+// in parallel, sorts every large sub-bucket as a task that goes on in
+// parallel, and groups runs of small sub-buckets into tasks of about
+// MSD_PAR_CUTOFF elements sorted serially, leaving the balancing to work
+// stealing. Small buckets use insertion sort.
+
+// Buckets at most this long are insertion sorted
+const INSERTION_CUTOFF: usize = 32;
+
+// Buckets at most this long are sorted serially, and tasks of small buckets
+// are about this long
+const MSD_PAR_CUTOFF: usize = 1 << 14;
+
+fn insertion_sort<K: RadixKey>(v: &mut [K], descending: bool) {
+    for i in 1..v.len() {
+        let x = v[i];
+        let key = sort_key(x, descending);
+        let mut j = i;
+        while j > 0 && sort_key(v[j - 1], descending) > key {
+            v[j] = v[j - 1];
+            j -= 1;
+        }
+        v[j] = x;
+    }
+}
+
+// Histogram of a large bucket, one per chunk summed together
+fn histogram_par<K: RadixKey>(v: &[K], descending: bool, shift: u32, mask: u64) -> Vec<usize> {
+    let chunk = std::cmp::max(
+        MSD_PAR_CUTOFF,
+        v.len().div_ceil(rayon::current_num_threads()),
+    );
+    v.par_chunks(chunk)
+        .map(|part| histogram(part, descending, shift, mask))
+        .reduce(
+            || vec![0; mask as usize + 1],
+            |mut counts, part_counts| {
+                counts
+                    .iter_mut()
+                    .zip(part_counts.iter())
+                    .for_each(|(count, part_count)| *count += part_count);
+                counts
+            },
+        )
+}
+
+// Cuts the buckets into tasks: every large bucket on its own, and runs of
+// small ones until they add up to MSD_PAR_CUTOFF elements
+fn msd_tasks<K>(buckets: Vec<&mut [K]>) -> Vec<Vec<&mut [K]>> {
+    let mut tasks: Vec<Vec<&mut [K]>> = Vec::new();
+    let mut task_len = MSD_PAR_CUTOFF;
+
+    for bucket in buckets {
+        if bucket.len() > MSD_PAR_CUTOFF || task_len + bucket.len() > MSD_PAR_CUTOFF {
+            tasks.push(Vec::new());
+            task_len = 0;
+        }
+        task_len += bucket.len();
+        tasks.last_mut().unwrap().push(bucket);
+    }
+
+    tasks
+}
+
+fn msd_sort<K: RadixKey>(
+    v: &mut [K],
+    radix_bits: u32,
+    descending: bool,
+    shift: u32,
+    parallel: bool,
+) {
+    if v.len() <= INSERTION_CUTOFF {
+        insertion_sort(v, descending);
+        return;
+    }
+
+    let mask = (1 << radix_bits) - 1;
+    let counts = if parallel {
+        histogram_par(v, descending, shift, mask)
+    } else {
+        histogram(v, descending, shift, mask)
+    };
+
+    // Permute in place: heads[d] is the next unplaced slot of bucket d
+    if !counts.contains(&v.len()) {
+        let mut heads = vec![0; counts.len()];
+        for d in 1..counts.len() {
+            heads[d] = heads[d - 1] + counts[d - 1];
+        }
+        let ends: Vec<usize> = heads
+            .iter()
+            .zip(counts.iter())
+            .map(|(h, c)| h + c)
+            .collect();
+
+        for d in 0..counts.len() {
+            while heads[d] < ends[d] {
+                let target = digit(v[heads[d]], descending, shift, mask);
+                if target == d {
+                    heads[d] += 1;
+                } else {
+                    v.swap(heads[d], heads[target]);
+                    heads[target] += 1;
+                }
+            }
+        }
+    }
+
+    if shift == 0 {
+        return;
+    }
+    let next_shift = shift - radix_bits;
+
+    let mut buckets: Vec<&mut [K]> = Vec::with_capacity(counts.len());
+    let mut rest = v;
+    for &count in counts.iter() {
+        let (head, tail) = rest.split_at_mut(count);
+        if count > 1 {
+            buckets.push(head);
+        }
+        rest = tail;
+    }
+
+    if parallel && buckets.len() > 1 {
+        msd_tasks(buckets).into_par_iter().for_each(|task| {
+            task.into_iter().for_each(|bucket| {
+                let parallel = bucket.len() > MSD_PAR_CUTOFF;
+                msd_sort(bucket, radix_bits, descending, next_shift, parallel)
+            })
+        });
+    } else {
+        buckets
+            .into_iter()
+            .for_each(|bucket| msd_sort(bucket, radix_bits, descending, next_shift, parallel));
+    }
+}
+
+// The most significant digit is the one left over at the top of the key
+fn top_shift<K: RadixKey>(radix_bits: u32) -> u32 {
+    (K::BITS.div_ceil(radix_bits) - 1) * radix_bits
+}
+
+pub fn msd_sort_serial<K: RadixKey>(v: &mut [K], radix_bits: u32, descending: bool) {
+    msd_sort(v, radix_bits, descending, top_shift::<K>(radix_bits), false);
+}
+
+pub fn msd_sort_par<K: RadixKey>(v: &mut [K], radix_bits: u32, descending: bool) {
+    msd_sort(v, radix_bits, descending, top_shift::<K>(radix_bits), true);
+}